@@ -0,0 +1,272 @@
+use std::sync::Arc;
+
+use actix_web::web::{Data, Path, Query, ServiceConfig};
+use actix_web::{get, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::pom::PomMetadata;
+use crate::project::Version;
+use crate::project_processor::jobs::Job;
+use crate::project_processor::queue::{BuildQueue, QueueEntry};
+use crate::report::project_report;
+use crate::repository::{Repository, Storage};
+use crate::search::sort_results;
+
+pub mod projects;
+
+/// Default amount of search results
+static SEARCH_LIMIT: usize = 25;
+/// The most search results that can be requested
+static MAX_SEARCH_LIMIT: usize = 200;
+
+/// Registers the JSON api. Must be registered before the javadoc routes. They would match the api paths
+pub fn register_web(service: &mut ServiceConfig) {
+    service
+        .service(get_job)
+        .service(search)
+        .service(get_report)
+        .service(projects::get_repositories)
+        // Before get_project. It would match the projects path
+        .service(projects::get_projects)
+        .service(projects::get_project)
+        .service(projects::get_versions)
+        .service(projects::rebuild_project);
+}
+
+/// Finds the repository by name
+pub(crate) fn find_repository(
+    repositories: &[Arc<Repository>],
+    name: &str,
+) -> actix_web::Result<Arc<Repository>> {
+    repositories
+        .iter()
+        .find(|repository| repository.name == name)
+        .cloned()
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Repository not found"))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobRequest {
+    pub repository: String,
+    pub project: String,
+    pub version: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildStatus {
+    Pending,
+    Running,
+    Failed,
+    /// The javadoc can be viewed
+    Ready,
+    /// The version or its javadoc does not exist upstream
+    NotAvailable,
+    /// Nothing is known about the version
+    Unknown,
+}
+
+impl BuildStatus {
+    /// Combines the job of the version with what is stored for it
+    pub fn new(job: Option<&Job>, stored: Option<&Version>) -> Self {
+        match (job, stored) {
+            (Some(Job::Pending { .. }), _) => BuildStatus::Pending,
+            (Some(Job::Running { .. }), _) => BuildStatus::Running,
+            (_, Some(Version::NoBuild { .. })) => BuildStatus::NotAvailable,
+            (_, Some(_)) => BuildStatus::Ready,
+            (Some(Job::Failed { .. }), None) => BuildStatus::Failed,
+            _ => BuildStatus::Unknown,
+        }
+    }
+}
+
+/// A stored version without the paths on this server
+#[derive(Debug, Serialize)]
+pub enum StoredVersion {
+    NoBuild {
+        checked: DateTime<Utc>,
+        attempts: u32,
+    },
+    Build {
+        sha1: Option<String>,
+        built: DateTime<Utc>,
+        storage: Storage,
+        last_accessed: Option<DateTime<Utc>>,
+        pom: Option<PomMetadata>,
+    },
+    BuildSnapshot {
+        sha1: Option<String>,
+        timestamp: DateTime<Utc>,
+        built: DateTime<Utc>,
+        storage: Storage,
+        last_accessed: Option<DateTime<Utc>>,
+        build: Option<String>,
+        /// The ids of the older builds that are kept. Newest first
+        history: Vec<String>,
+        pom: Option<PomMetadata>,
+    },
+}
+
+impl From<&Version> for StoredVersion {
+    fn from(version: &Version) -> Self {
+        match version.clone() {
+            Version::NoBuild { checked, attempts } => StoredVersion::NoBuild { checked, attempts },
+            Version::Build {
+                sha1,
+                built,
+                storage,
+                last_accessed,
+                pom,
+                ..
+            } => StoredVersion::Build {
+                sha1,
+                built,
+                storage,
+                last_accessed,
+                pom,
+            },
+            Version::BuildSnapshot {
+                sha1,
+                timestamp,
+                built,
+                storage,
+                last_accessed,
+                build,
+                history,
+                pom,
+                ..
+            } => StoredVersion::BuildSnapshot {
+                sha1,
+                timestamp,
+                built,
+                storage,
+                last_accessed,
+                build,
+                history: history.into_iter().map(|build| build.id).collect(),
+                pom,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobStatus {
+    pub repository: String,
+    pub project: String,
+    pub version: String,
+    pub status: BuildStatus,
+    pub job: Option<Job>,
+    pub stored: Option<StoredVersion>,
+}
+
+#[get("/api/jobs/{repository}/{project}/{version}")]
+pub async fn get_job(
+    queue: Data<BuildQueue>,
+    repositories: Data<Vec<Arc<Repository>>>,
+    request: Path<JobRequest>,
+) -> actix_web::Result<HttpResponse> {
+    let request = request.into_inner();
+    let repository = find_repository(&repositories, &request.repository)?;
+    let project = repository.get_project(&request.project).await?;
+    // latest is queued without a version until the project is known
+    let (version, stored) = if request.version.eq("latest") {
+        let latest = project.as_ref().and_then(|project| project.latest.clone());
+        let stored = project
+            .as_ref()
+            .and_then(|project| project.get_latest_version().cloned());
+        (latest, stored)
+    } else {
+        let stored = project
+            .as_ref()
+            .and_then(|project| project.versions.get(&request.version).cloned());
+        (Some(request.version.clone()), stored)
+    };
+
+    let mut job = None;
+    if version.is_some() {
+        job = queue
+            .job(&QueueEntry {
+                repository: repository.name.clone(),
+                project: request.project.clone(),
+                version: version.clone(),
+            })
+            .await;
+    }
+    if job.is_none() && request.version.eq("latest") {
+        job = queue
+            .job(&QueueEntry {
+                repository: repository.name.clone(),
+                project: request.project.clone(),
+                version: None,
+            })
+            .await;
+    }
+
+    let status = BuildStatus::new(job.as_ref(), stored.as_ref());
+    Ok(HttpResponse::Ok().json(JobStatus {
+        repository: repository.name.clone(),
+        project: request.project,
+        version: version.unwrap_or(request.version),
+        status,
+        job,
+        stored: stored.as_ref().map(StoredVersion::from),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    /// Only search this repository
+    pub repository: Option<String>,
+    /// Only search this project
+    pub project: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[get("/api/search")]
+pub async fn search(
+    repositories: Data<Vec<Arc<Repository>>>,
+    query: Query<SearchQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let query = query.into_inner();
+    let q = query.q.trim();
+    if q.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("Query can not be empty"));
+    }
+    let limit = query.limit.unwrap_or(SEARCH_LIMIT).min(MAX_SEARCH_LIMIT);
+    let mut results = Vec::new();
+    for repository in repositories.iter() {
+        if let Some(name) = query.repository.as_ref() {
+            if name.ne(&repository.name) {
+                continue;
+            }
+        }
+        results.extend(
+            repository
+                .search
+                .search(repository, q, query.project.as_deref(), limit),
+        );
+    }
+    sort_results(&mut results);
+    results.truncate(limit);
+    Ok(HttpResponse::Ok().json(results))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportRequest {
+    pub repository: String,
+    pub project: String,
+    pub version: String,
+}
+
+/// The deprecated elements and the `@since` tags of a version
+#[get("/api/report/{repository}/{project}/{version}")]
+pub async fn get_report(
+    repositories: Data<Vec<Arc<Repository>>>,
+    request: Path<ReportRequest>,
+) -> actix_web::Result<HttpResponse> {
+    let repository = find_repository(&repositories, &request.repository)?;
+    let report = project_report(&repository, &request.project, &request.version).await?;
+    Ok(HttpResponse::Ok().json(report))
+}
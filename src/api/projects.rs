@@ -0,0 +1,292 @@
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use actix_web::web::{Bytes, Data, Path, Query};
+use actix_web::{get, post, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::api::{find_repository, BuildStatus, StoredVersion};
+use crate::hooks::check_token;
+use crate::project::{Project, ProjectListing, Version};
+use crate::project_processor::jobs::Job;
+use crate::project_processor::queue::{BuildQueue, QueueEntry};
+use crate::project_processor::ProjectRequest;
+use crate::repository::{Repository, Storage};
+use crate::version::MavenVersion;
+use crate::HookSettings;
+
+/// Default amount of projects in a page
+static PROJECTS_LIMIT: usize = 100;
+/// The most projects that can be requested at once
+static MAX_PROJECTS_LIMIT: usize = 1000;
+/// maven-metadata.xml files read at the same time for a page of projects
+static METADATA_READS: usize = 16;
+
+#[derive(Debug, Serialize)]
+pub struct RepositoryInfo {
+    pub name: String,
+    pub address: String,
+    pub allows_redeploy: bool,
+    pub storage: Storage,
+    /// Amount of cached projects
+    pub projects: usize,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectInfo {
+    pub repository: String,
+    pub name: String,
+    /// The latest version that was resolved on this server
+    pub latest: Option<String>,
+    /// The latest version in the saved maven-metadata.xml
+    pub upstream_latest: Option<String>,
+    pub last_updated: Option<DateTime<Utc>>,
+    pub last_accessed: Option<DateTime<Utc>>,
+    /// Amount of versions with a javadoc
+    pub built: usize,
+    pub url: String,
+}
+
+impl ProjectInfo {
+    pub async fn new(repository: &Repository, project: ProjectListing) -> Self {
+        let upstream_latest = Project {
+            name: project.name.clone(),
+            ..Default::default()
+        }
+        .get_deploy_data(repository)
+        .await
+        .ok()
+        .and_then(|deploy_data| deploy_data.get_latest_version().cloned());
+        ProjectInfo {
+            repository: repository.name.clone(),
+            url: format!("{}/{}", repository.url_prefix, project.name),
+            name: project.name,
+            latest: project.latest,
+            upstream_latest,
+            last_updated: project.last_updated,
+            last_accessed: project.last_accessed,
+            built: project.built,
+        }
+    }
+}
+
+/// One page of the projects of a repository
+#[derive(Debug, Serialize)]
+pub struct ProjectList {
+    /// Amount of cached projects
+    pub total: usize,
+    pub offset: usize,
+    pub projects: Vec<ProjectInfo>,
+}
+
+/// A version known from the maven-metadata.xml or the cache
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+    /// The version or `{snapshot}@{build}` for kept snapshot builds
+    pub name: String,
+    pub status: BuildStatus,
+    pub job: Option<Job>,
+    pub built: Option<DateTime<Utc>>,
+    /// The javadoc. None if it is not built
+    pub url: Option<String>,
+    pub stored: Option<StoredVersion>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectDetails {
+    #[serde(flatten)]
+    pub info: ProjectInfo,
+    pub versions: Vec<VersionInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProjectPath {
+    pub repository: String,
+    pub project: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProjectsQuery {
+    #[serde(default)]
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+/// Picks the version to rebuild. The latest if missing
+#[derive(Debug, Deserialize)]
+pub struct RebuildQuery {
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueuedBuild {
+    pub repository: String,
+    pub project: String,
+    pub version: Option<String>,
+    /// False if the same request was already pending or running. It is rebuilt anyway
+    pub queued: bool,
+    pub job: Option<Job>,
+}
+
+/// Every version in the maven-metadata.xml and the cache with its build state. Newest first
+pub async fn version_infos(
+    repository: &Repository,
+    queue: &BuildQueue,
+    project: &Project,
+) -> Vec<VersionInfo> {
+    let mut names: BTreeSet<String> = project.versions.keys().cloned().collect();
+    if let Ok(deploy_data) = project.get_deploy_data(repository).await {
+        names.extend(deploy_data.versioning.versions.version);
+    }
+    let mut names: Vec<String> = names.into_iter().collect();
+    names.sort_by_cached_key(|name| Reverse(MavenVersion::parse(name)));
+    let mut versions = Vec::with_capacity(names.len());
+    for name in names.iter() {
+        let stored = project.versions.get(name);
+        let job = queue
+            .job(&QueueEntry {
+                repository: repository.name.clone(),
+                project: project.name.clone(),
+                version: Some(name.clone()),
+            })
+            .await;
+        let mut builds = vec![(name.clone(), stored.cloned())];
+        if let Some(Version::BuildSnapshot { history, .. }) = stored {
+            builds.extend(
+                history
+                    .iter()
+                    .map(|build| (format!("{name}@{}", build.id), Some(build.as_version()))),
+            );
+        }
+        for (name, stored) in builds {
+            let built = match &stored {
+                Some(Version::Build { built, .. } | Version::BuildSnapshot { built, .. }) => {
+                    Some(*built)
+                }
+                _ => None,
+            };
+            let has_javadoc = stored.as_ref().and_then(Version::path).is_some();
+            versions.push(VersionInfo {
+                status: BuildStatus::new(job.as_ref(), stored.as_ref()),
+                job: job.clone(),
+                built,
+                url: has_javadoc.then(|| repository.javadoc_url(&project.name, &name, "")),
+                stored: stored.as_ref().map(StoredVersion::from),
+                name,
+            });
+        }
+    }
+    versions
+}
+
+async fn find_project(repository: &Repository, project: &str) -> actix_web::Result<Project> {
+    repository
+        .get_project(project)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Project not found"))
+}
+
+#[get("/api/repositories")]
+pub async fn get_repositories(
+    repositories: Data<Vec<Arc<Repository>>>,
+) -> actix_web::Result<HttpResponse> {
+    let mut result = Vec::with_capacity(repositories.len());
+    for repository in repositories.iter() {
+        result.push(RepositoryInfo {
+            name: repository.name.clone(),
+            address: repository.address.clone(),
+            allows_redeploy: repository.allows_redeploy,
+            storage: repository.storage,
+            projects: repository.project_count()?,
+            url: format!("{}/", repository.url_prefix),
+        });
+    }
+    Ok(HttpResponse::Ok().json(result))
+}
+
+/// The projects ordered by name. `offset` and `limit` page through them
+#[get("/api/{repository}/projects")]
+pub async fn get_projects(
+    repositories: Data<Vec<Arc<Repository>>>,
+    repository: Path<String>,
+    query: Query<ProjectsQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let repository = find_repository(&repositories, &repository)?;
+    let projects = repository.project_listings()?;
+    let total = projects.len();
+    let limit = query.limit.unwrap_or(PROJECTS_LIMIT).min(MAX_PROJECTS_LIMIT);
+    let projects = futures_util::stream::iter(projects.into_iter().skip(query.offset).take(limit))
+        .map(|project| ProjectInfo::new(&repository, project))
+        .buffered(METADATA_READS)
+        .collect()
+        .await;
+    Ok(HttpResponse::Ok().json(ProjectList {
+        total,
+        offset: query.offset,
+        projects,
+    }))
+}
+
+#[get("/api/{repository}/{project}")]
+pub async fn get_project(
+    queue: Data<BuildQueue>,
+    repositories: Data<Vec<Arc<Repository>>>,
+    path: Path<ProjectPath>,
+) -> actix_web::Result<HttpResponse> {
+    let repository = find_repository(&repositories, &path.repository)?;
+    let project = find_project(&repository, &path.project).await?;
+    Ok(HttpResponse::Ok().json(ProjectDetails {
+        info: ProjectInfo::new(&repository, ProjectListing::from(&project)).await,
+        versions: version_infos(&repository, &queue, &project).await,
+    }))
+}
+
+#[get("/api/{repository}/{project}/versions")]
+pub async fn get_versions(
+    queue: Data<BuildQueue>,
+    repositories: Data<Vec<Arc<Repository>>>,
+    path: Path<ProjectPath>,
+) -> actix_web::Result<HttpResponse> {
+    let repository = find_repository(&repositories, &path.repository)?;
+    let project = find_project(&repository, &path.project).await?;
+    Ok(HttpResponse::Ok().json(version_infos(&repository, &queue, &project).await))
+}
+
+/// Rebuilds the version even if the cache rules say it is fresh. Requires the hook token like `/hooks/deploy`
+#[post("/api/{repository}/{project}")]
+pub async fn rebuild_project(
+    request: HttpRequest,
+    body: Bytes,
+    hooks: Data<HookSettings>,
+    queue: Data<BuildQueue>,
+    repositories: Data<Vec<Arc<Repository>>>,
+    path: Path<ProjectPath>,
+    query: Query<RebuildQuery>,
+) -> actix_web::Result<HttpResponse> {
+    check_token(&request, &body, &hooks)?;
+    let path = path.into_inner();
+    let repository = find_repository(&repositories, &path.repository)?;
+    let request = ProjectRequest {
+        repository: repository.clone(),
+        project: path.project,
+        version: query.into_inner().version,
+        force: true,
+    };
+    let entry = QueueEntry::from(&request);
+    let queued = queue
+        .send(request)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to send request"))?;
+    let job = queue.job(&entry).await;
+    Ok(HttpResponse::Accepted().json(QueuedBuild {
+        repository: entry.repository,
+        project: entry.project,
+        version: entry.version,
+        queued,
+        job,
+    }))
+}
@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+
+use crate::project::{Project, ReplacedBuild, Version};
+use crate::project_processor::queue::BuildQueue;
+use crate::repository::{project_to_path, Repository};
+use crate::report::report_file;
+use crate::search::index_file;
+use crate::{metrics, Error};
+
+static MEGABYTE: u64 = 1024 * 1024;
+/// How long the files of a replaced build are kept for requests that were already reading them
+static REPLACED_GRACE_MINUTES: i64 = 10;
+
+/// Page views since the last sweep. Kept in memory so viewing a page does not write to the database
+#[derive(Debug, Default)]
+pub struct AccessLog {
+    accessed: Mutex<HashMap<(String, String), DateTime<Utc>>>,
+}
+
+impl AccessLog {
+    pub fn touch(&self, project: &str, version: &str) {
+        self.accessed
+            .lock()
+            .unwrap()
+            .insert((project.to_string(), version.to_string()), Utc::now());
+    }
+
+    fn take(&self) -> HashMap<(String, String), DateTime<Utc>> {
+        std::mem::take(&mut *self.accessed.lock().unwrap())
+    }
+}
+
+/// A built version that can be evicted
+struct Candidate {
+    repository: Arc<Repository>,
+    project: String,
+    version: String,
+    last_used: DateTime<Utc>,
+    size: u64,
+}
+
+/// Runs [sweep] every `interval` minutes
+pub async fn sweeper(
+    repositories: Vec<Arc<Repository>>,
+    queue: Arc<BuildQueue>,
+    max_size: Option<u64>,
+    interval: u64,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(interval.max(1) * 60));
+    loop {
+        interval.tick().await;
+        if let Err(error) = sweep(&repositories, &queue, max_size).await {
+            error!("Failed to sweep the cache {error}");
+        }
+    }
+}
+
+/// Saves the access times and evicts the least recently used versions until every quota is met
+///
+/// `max_size` is the quota of the whole cache in megabytes. Each repository can have its own in [crate::repository::CacheRules].
+/// Projects with a pending or running build are never evicted
+pub async fn sweep(
+    repositories: &[Arc<Repository>],
+    queue: &BuildQueue,
+    max_size: Option<u64>,
+) -> Result<(), Error> {
+    let mut candidates = Vec::new();
+    let mut total = 0;
+    for repository in repositories {
+        flush_access(repository).await?;
+        let mut repository_candidates = Vec::new();
+        let mut repository_total = 0;
+        for project in repository.list_projects()? {
+            if !project.replaced.is_empty() {
+                remove_replaced(repository, queue, &project).await?;
+            }
+            let project_location = repository.path.join(project_to_path(&project.name));
+            for (version, value) in project.versions.iter() {
+                let last_used = match value.last_used() {
+                    Some(last_used) => last_used,
+                    None => continue,
+                };
+                let size = version_size(&project_location, version, value).await?;
+                repository_total += size;
+                if !is_pinned(repository, &project, version) {
+                    repository_candidates.push(Candidate {
+                        repository: repository.clone(),
+                        project: project.name.clone(),
+                        version: version.clone(),
+                        last_used,
+                        size,
+                    });
+                }
+            }
+        }
+        metrics::CACHE_SIZE
+            .with_label_values(&[&repository.name])
+            .set(repository_total as i64);
+        if let Some(max_size) = repository.cache.max_size {
+            repository_total = evict(
+                queue,
+                &mut repository_candidates,
+                repository_total,
+                max_size * MEGABYTE,
+            )
+            .await?;
+            metrics::CACHE_SIZE
+                .with_label_values(&[&repository.name])
+                .set(repository_total as i64);
+        }
+        total += repository_total;
+        candidates.extend(repository_candidates);
+    }
+    if let Some(max_size) = max_size {
+        evict(queue, &mut candidates, total, max_size * MEGABYTE).await?;
+    }
+    Ok(())
+}
+
+/// Removes the replaced builds of the project once their grace period is over
+async fn remove_replaced(
+    repository: &Repository,
+    queue: &BuildQueue,
+    project: &Project,
+) -> Result<(), Error> {
+    // A running build could be replacing the same files
+    if queue.busy(&repository.name, &project.name).await {
+        return Ok(());
+    }
+    let expired = Utc::now() - chrono::Duration::minutes(REPLACED_GRACE_MINUTES);
+    let removed: Vec<ReplacedBuild> = project
+        .replaced
+        .iter()
+        .filter(|build| build.replaced < expired)
+        .cloned()
+        .collect();
+    if removed.is_empty() {
+        return Ok(());
+    }
+    for build in removed.iter() {
+        remove_file(repository, &build.path).await;
+    }
+    repository
+        .update_project(&project.name, |project| {
+            project.replaced.retain(|build| !removed.contains(build))
+        })
+        .await?;
+    Ok(())
+}
+
+/// The latest version and anything listed in [crate::repository::CacheRules::pinned] is never evicted
+fn is_pinned(repository: &Repository, project: &Project, version: &str) -> bool {
+    if project.latest.as_deref() == Some(version) {
+        return true;
+    }
+    repository.cache.pinned.iter().any(|pinned| {
+        pinned.eq(&project.name) || pinned.eq(&format!("{}:{version}", project.name))
+    })
+}
+
+/// Evicts the least recently used candidates until the total is under the max. Returns the new total
+///
+/// Candidates whose project is being built are kept. The build can be reading or replacing their files
+async fn evict(
+    queue: &BuildQueue,
+    candidates: &mut Vec<Candidate>,
+    mut total: u64,
+    max: u64,
+) -> Result<u64, Error> {
+    if total <= max {
+        return Ok(total);
+    }
+    candidates.sort_by_key(|candidate| candidate.last_used);
+    let mut kept = Vec::with_capacity(candidates.len());
+    for candidate in candidates.drain(..) {
+        if total <= max || queue.busy(&candidate.repository.name, &candidate.project).await {
+            kept.push(candidate);
+            continue;
+        }
+        info!(
+            "Evicting {} {} from {}. Last used {}",
+            candidate.project, candidate.version, candidate.repository.name, candidate.last_used
+        );
+        remove_version(&candidate.repository, &candidate.project, &candidate.version).await?;
+        total = total.saturating_sub(candidate.size);
+    }
+    *candidates = kept;
+    Ok(total)
+}
+
+/// Removes the version from the database, the search index and the disk
+pub async fn remove_version(
+    repository: &Repository,
+    project: &str,
+    version: &str,
+) -> Result<(), Error> {
+    let mut removed = None;
+    repository
+        .update_project(project, |value| removed = value.versions.remove(version))
+        .await?;
+    let removed = match removed {
+        Some(removed) => removed,
+        None => return Ok(()),
+    };
+    repository.search.remove(project, version);
+
+    let project_location = repository.path.join(project_to_path(project));
+    let version = version.to_string();
+    let files = blocking(move || version_files(&project_location, &version, &removed)).await?;
+    for file in files {
+        remove_file(repository, &file).await;
+    }
+    Ok(())
+}
+
+/// Removes a file or folder of the cache. A removed jar is dropped from the [crate::zip::JarCache]
+async fn remove_file(repository: &Repository, file: &Path) {
+    let result = if file.is_dir() {
+        tokio::fs::remove_dir_all(file).await
+    } else {
+        repository.jars.invalidate(file);
+        tokio::fs::remove_file(file).await
+    };
+    match result {
+        Ok(()) => {}
+        Err(error) if error.kind() == ErrorKind::NotFound => {}
+        Err(error) => warn!("Failed to remove {}: {error}", file.display()),
+    }
+}
+
+/// Writes the access times collected since the last sweep to the database
+async fn flush_access(repository: &Repository) -> Result<(), Error> {
+    let mut projects: HashMap<String, Vec<(String, DateTime<Utc>)>> = HashMap::new();
+    for ((project, version), accessed) in repository.access.take() {
+        projects.entry(project).or_default().push((version, accessed));
+    }
+    for (name, versions) in projects {
+        repository
+            .update_project(&name, |project| {
+                for (version, accessed) in versions.iter() {
+                    if let Some(value) = project.versions.get_mut(version) {
+                        value.set_last_accessed(*accessed);
+                    }
+                    if project.last_accessed.map(|last| last < *accessed).unwrap_or(true) {
+                        project.last_accessed = Some(*accessed);
+                    }
+                }
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+/// The [disk_usage] of the [version_files]. Walks the disk on the blocking pool
+pub async fn version_size(
+    project_location: &Path,
+    version: &str,
+    value: &Version,
+) -> Result<u64, Error> {
+    let project_location = project_location.to_path_buf();
+    let (version, value) = (version.to_string(), value.clone());
+    blocking(move || {
+        version_files(&project_location, &version, &value)
+            .iter()
+            .map(|file| disk_usage(file))
+            .sum()
+    })
+    .await
+}
+
+async fn blocking<T: Send + 'static>(
+    task: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, Error> {
+    tokio::task::spawn_blocking(task)
+        .await
+        .map_err(|error| std::io::Error::new(ErrorKind::Other, error).into())
+}
+
+/// Everything on disk that belongs to the version. Only returns files that exist
+pub fn version_files(project_location: &Path, version: &str, value: &Version) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = value.paths().into_iter().cloned().collect();
+    files.push(project_location.join(version));
+    files.push(project_location.join(format!("{version}.jar")));
+    files.push(index_file(project_location, version));
+    files.push(report_file(project_location, version));
+    if let Version::BuildSnapshot { history, .. } = value {
+        files.extend(
+            history
+                .iter()
+                .map(|build| report_file(project_location, &format!("{version}@{}", build.id))),
+        );
+    }
+    if let Some(base) = version.strip_suffix("-SNAPSHOT") {
+        // Snapshot jars are named after the timestamped version
+        if let Ok(entries) = std::fs::read_dir(project_location) {
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                if is_snapshot_jar(base, &name.to_string_lossy()) {
+                    files.push(entry.path());
+                }
+            }
+        }
+    }
+    files.sort();
+    files.dedup();
+    files.retain(|file| file.exists());
+    files
+}
+
+/// Matches `{base}-{yyyyMMdd.HHmmss}-{build}.jar`
+fn is_snapshot_jar(base: &str, name: &str) -> bool {
+    let timestamp = match name
+        .strip_prefix(base)
+        .and_then(|name| name.strip_prefix('-'))
+        .and_then(|name| name.strip_suffix(".jar"))
+    {
+        Some(timestamp) => timestamp,
+        None => return false,
+    };
+    match timestamp.split_once('-') {
+        Some((time, build)) => {
+            time.len() == 15
+                && time
+                    .chars()
+                    .enumerate()
+                    .all(|(i, c)| if i == 8 { c == '.' } else { c.is_ascii_digit() })
+                && !build.is_empty()
+                && build.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+/// Size of a file or everything inside a folder in bytes
+pub fn disk_usage(path: &Path) -> u64 {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| disk_usage(&entry.path()))
+                .sum()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,292 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use clap::Subcommand;
+
+use crate::cache::{disk_usage, remove_version, version_files};
+use crate::project::{Project, Version};
+use crate::repository::{open_repositories, project_to_path, Repository, Storage};
+use crate::zip::JarIndex;
+use crate::{Config, Error};
+
+/// Works on the files the server writes. The server has to be stopped. It holds the lock on the database
+#[derive(Subcommand)]
+pub enum CacheCommand {
+    /// List the cached projects
+    List {
+        /// Only list this repository
+        repository: Option<String>,
+    },
+    /// Show the versions of a project
+    Show { repository: String, project: String },
+    /// Remove a version, a project or everything cached for a repository
+    Purge {
+        repository: String,
+        project: Option<String>,
+        version: Option<String>,
+    },
+    /// Find builds with missing files and files that no project references
+    Verify {
+        /// Only verify this repository
+        repository: Option<String>,
+        /// Remove the broken versions and the orphaned files
+        #[clap(long)]
+        fix: bool,
+    },
+}
+
+/// Runs the command. Returns false if it failed or found problems
+pub async fn cache_command(mut config: Config, command: CacheCommand) -> Result<bool, Error> {
+    let filter = match &command {
+        CacheCommand::List { repository } | CacheCommand::Verify { repository, .. } => {
+            repository.clone()
+        }
+        CacheCommand::Show { repository, .. } | CacheCommand::Purge { repository, .. } => {
+            Some(repository.clone())
+        }
+    };
+    let mut repositories = open_repositories(&mut config)?;
+    if let Some(name) = filter.as_ref() {
+        repositories.retain(|repository| repository.name.eq(name));
+        if repositories.is_empty() {
+            println!("Repository {name} not found");
+            return Ok(false);
+        }
+    }
+
+    match command {
+        CacheCommand::List { .. } => {
+            for repository in repositories.iter() {
+                list(repository)?;
+            }
+            Ok(true)
+        }
+        CacheCommand::Show { project, .. } => show(&repositories[0], &project).await,
+        CacheCommand::Purge {
+            project, version, ..
+        } => purge(&repositories[0], project, version).await,
+        CacheCommand::Verify { fix, .. } => {
+            let mut problems = 0;
+            for repository in repositories.iter() {
+                problems += verify(repository, fix).await?;
+            }
+            println!("Found {problems} problems");
+            Ok(problems == 0 || fix)
+        }
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+fn project_size(repository: &Repository, project: &Project) -> u64 {
+    let project_location = repository.path.join(project_to_path(&project.name));
+    project
+        .versions
+        .iter()
+        .flat_map(|(version, value)| version_files(&project_location, version, value))
+        .map(|file| disk_usage(&file))
+        .sum()
+}
+
+fn list(repository: &Repository) -> Result<(), Error> {
+    let mut projects = repository.list_projects()?;
+    projects.sort_by(|a, b| a.name.cmp(&b.name));
+    for project in projects {
+        println!(
+            "{} {} latest: {} versions: {} size: {}",
+            repository.name,
+            project.name,
+            project.latest.as_deref().unwrap_or("unknown"),
+            project.versions.len(),
+            format_size(project_size(repository, &project))
+        );
+    }
+    Ok(())
+}
+
+async fn show(repository: &Repository, name: &str) -> Result<bool, Error> {
+    let project = match repository.get_project(name).await? {
+        Some(project) => project,
+        None => {
+            println!("{name} is not cached in {}", repository.name);
+            return Ok(false);
+        }
+    };
+    let project_location = repository.path.join(project_to_path(&project.name));
+    println!("{} in {}", project.name, repository.name);
+    println!("Latest: {}", project.latest.as_deref().unwrap_or("unknown"));
+    if let Some(last_updated) = project.last_updated {
+        println!("Last updated: {last_updated}");
+    }
+    if let Some(last_accessed) = project.last_accessed {
+        println!("Last accessed: {last_accessed}");
+    }
+    let mut versions: Vec<_> = project.versions.iter().collect();
+    versions.sort_by(|a, b| a.0.cmp(b.0));
+    for (version, value) in versions {
+        match value {
+            Version::NoBuild { checked, attempts } => {
+                println!("  {version} no javadoc. Checked {checked} {attempts} times in a row");
+            }
+            Version::Build { built, storage, .. }
+            | Version::BuildSnapshot { built, storage, .. } => {
+                let size: u64 = version_files(&project_location, version, value)
+                    .iter()
+                    .map(|file| disk_usage(file))
+                    .sum();
+                let storage = match storage {
+                    Storage::Extract => "extracted",
+                    Storage::Jar => "jar",
+                };
+                println!(
+                    "  {version} built {built} {storage} size: {}",
+                    format_size(size)
+                );
+            }
+        }
+    }
+    Ok(true)
+}
+
+async fn purge(
+    repository: &Repository,
+    project: Option<String>,
+    version: Option<String>,
+) -> Result<bool, Error> {
+    let project = match project {
+        Some(project) => project,
+        None => {
+            for project in repository.list_projects()? {
+                repository.remove_project(&project.name).await?;
+            }
+            if repository.path.exists() {
+                tokio::fs::remove_dir_all(&repository.path).await?;
+            }
+            println!("Purged {}", repository.name);
+            return Ok(true);
+        }
+    };
+    let value = match repository.get_project(&project).await? {
+        Some(value) => value,
+        None => {
+            println!("{project} is not cached in {}", repository.name);
+            return Ok(false);
+        }
+    };
+    if let Some(version) = version {
+        if !value.versions.contains_key(&version) {
+            println!("{project} {version} is not cached in {}", repository.name);
+            return Ok(false);
+        }
+        remove_version(repository, &project, &version).await?;
+        println!("Purged {project} {version}");
+        return Ok(true);
+    }
+    for version in value.versions.keys() {
+        remove_version(repository, &project, version).await?;
+    }
+    for build in value.replaced.iter() {
+        if build.path.is_dir() {
+            tokio::fs::remove_dir_all(&build.path).await?;
+        } else if build.path.exists() {
+            tokio::fs::remove_file(&build.path).await?;
+        }
+    }
+    repository.remove_project(&project).await?;
+    // The folder can contain other projects. Only the files of this project are removed
+    let project_location = repository.path.join(project_to_path(&project));
+    let metadata = project_location.join("maven-metadata.xml");
+    if metadata.exists() {
+        tokio::fs::remove_file(metadata).await?;
+    }
+    if project_location.exists() && std::fs::read_dir(&project_location)?.next().is_none() {
+        tokio::fs::remove_dir(&project_location).await?;
+    }
+    println!("Purged {project}");
+    Ok(true)
+}
+
+/// Returns the amount of problems found
+async fn verify(repository: &Repository, fix: bool) -> Result<usize, Error> {
+    let mut problems = 0;
+    let mut referenced = HashSet::new();
+    for project in repository.list_projects()? {
+        let project_location = repository.path.join(project_to_path(&project.name));
+        referenced.insert(project_location.join("maven-metadata.xml"));
+        // Removed by the sweeper once their grace period is over
+        referenced.extend(project.replaced.iter().map(|build| build.path.clone()));
+        for (version, value) in project.versions.iter() {
+            referenced.extend(version_files(&project_location, version, value));
+            let problem = match value {
+                Version::NoBuild { .. } => None,
+                Version::Build { path, storage, .. }
+                | Version::BuildSnapshot { path, storage, .. } => check_build(path, storage),
+            };
+            if let Some(problem) = problem {
+                problems += 1;
+                println!(
+                    "BROKEN {} {} {version}: {problem}",
+                    repository.name, project.name
+                );
+                if fix {
+                    remove_version(repository, &project.name, version).await?;
+                }
+            }
+        }
+    }
+    if !repository.path.exists() {
+        return Ok(problems);
+    }
+    let mut orphans = Vec::new();
+    find_orphans(&repository.path, &referenced, &mut orphans)?;
+    for orphan in orphans {
+        problems += 1;
+        println!("ORPHAN {} {}", repository.name, orphan.display());
+        if fix {
+            if orphan.is_dir() {
+                tokio::fs::remove_dir_all(&orphan).await?;
+            } else {
+                tokio::fs::remove_file(&orphan).await?;
+            }
+        }
+    }
+    Ok(problems)
+}
+
+/// Describes what is wrong with the stored javadoc
+fn check_build(path: &Path, storage: &Storage) -> Option<String> {
+    if !path.exists() {
+        return Some(format!("{} does not exist", path.display()));
+    }
+    match storage {
+        Storage::Extract if !path.join("index.html").exists() => {
+            Some(format!("{} has no index.html", path.display()))
+        }
+        Storage::Jar => JarIndex::load(path)
+            .err()
+            .map(|error| format!("{} can not be read: {error}", path.display())),
+        _ => None,
+    }
+}
+
+/// Files and folders that are not referenced and do not contain anything referenced
+fn find_orphans(
+    folder: &Path,
+    referenced: &HashSet<PathBuf>,
+    orphans: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    for entry in std::fs::read_dir(folder)? {
+        let path = entry?.path();
+        if referenced.contains(&path) {
+            continue;
+        }
+        if path.is_dir() && referenced.iter().any(|file| file.starts_with(&path)) {
+            find_orphans(&path, referenced, orphans)?;
+        } else {
+            orphans.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use log::{info, warn};
+
+use crate::project::{Project, ProjectListing};
+use crate::Error;
+
+/// Every repository has its own tree of projects keyed by the project name
+static PROJECTS_TREE: &str = "projects";
+/// Repositories that had their project.json files imported
+static MIGRATIONS_TREE: &str = "migrations";
+/// How sled reports that another process holds the database
+static LOCKED: &str = "could not acquire lock";
+
+/// The embedded store of project and version metadata. Lives in `{cache}/database`
+#[derive(Clone)]
+pub struct Database {
+    db: sled::Db,
+}
+
+impl Database {
+    /// Only one process can open the database. The commands fail with [Error::DatabaseLocked] while the server runs
+    pub fn open(cache: &Path) -> Result<Self, Error> {
+        let path = cache.join("database");
+        let db = sled::open(&path).map_err(|error| match error {
+            sled::Error::Io(error) if error.to_string().starts_with(LOCKED) => {
+                Error::DatabaseLocked(path.clone())
+            }
+            error => error.into(),
+        })?;
+        Ok(Self { db })
+    }
+
+    /// Opens the projects of the repository. The first time the project.json files are imported
+    pub fn projects(&self, repository: &str, path: &Path) -> Result<ProjectStore, Error> {
+        let tree = self.db.open_tree(format!("{PROJECTS_TREE}/{repository}"))?;
+        let store = ProjectStore {
+            tree,
+            listings: Arc::default(),
+        };
+        let migrations = self.db.open_tree(MIGRATIONS_TREE)?;
+        if !migrations.contains_key(repository)? {
+            import_project_files(&store, path)?;
+            migrations.insert(repository, "project.json")?;
+            self.db.flush()?;
+        }
+        Ok(store)
+    }
+}
+
+/// The projects of one repository
+#[derive(Clone)]
+pub struct ProjectStore {
+    tree: sled::Tree,
+    /// Read from the tree on first use and kept up to date by every write
+    listings: Arc<RwLock<Option<BTreeMap<String, ProjectListing>>>>,
+}
+
+impl std::fmt::Debug for ProjectStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProjectStore")
+            .field("projects", &self.tree.len())
+            .finish()
+    }
+}
+
+impl ProjectStore {
+    pub fn get(&self, project: &str) -> Result<Option<Project>, Error> {
+        match self.tree.get(project)? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn insert(&self, project: &Project) -> Result<(), Error> {
+        self.tree
+            .insert(project.name.as_str(), serde_json::to_vec(project)?)?;
+        self.update_listing(&project.name, Some(project));
+        Ok(())
+    }
+
+    pub fn remove(&self, project: &str) -> Result<Option<Project>, Error> {
+        let removed = self.tree.remove(project)?;
+        self.update_listing(project, None);
+        match removed {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Changes the project without losing a save that happened in between reading and writing.
+    /// Returns the updated project. None if the project does not exist
+    pub fn update(
+        &self,
+        project: &str,
+        mut update: impl FnMut(&mut Project),
+    ) -> Result<Option<Project>, Error> {
+        loop {
+            let current = match self.tree.get(project)? {
+                Some(current) => current,
+                None => return Ok(None),
+            };
+            let mut value: Project = serde_json::from_slice(&current)?;
+            update(&mut value);
+            let new = serde_json::to_vec(&value)?;
+            if self
+                .tree
+                .compare_and_swap(project, Some(current), Some(new))?
+                .is_ok()
+            {
+                self.update_listing(project, Some(&value));
+                return Ok(Some(value));
+            }
+        }
+    }
+
+    /// The listing of every project ordered by name. Only the first call reads the projects
+    pub fn listings(&self) -> Result<Vec<ProjectListing>, Error> {
+        if let Some(listings) = self.listings.read().unwrap().as_ref() {
+            return Ok(listings.values().cloned().collect());
+        }
+        let mut listings = self.listings.write().unwrap();
+        if listings.is_none() {
+            *listings = Some(
+                self.list()?
+                    .iter()
+                    .map(|project| (project.name.clone(), ProjectListing::from(project)))
+                    .collect(),
+            );
+        }
+        Ok(listings.iter().flat_map(BTreeMap::values).cloned().collect())
+    }
+
+    /// Amount of projects in the repository
+    pub fn count(&self) -> Result<usize, Error> {
+        if let Some(listings) = self.listings.read().unwrap().as_ref() {
+            return Ok(listings.len());
+        }
+        Ok(self.listings()?.len())
+    }
+
+    fn update_listing(&self, name: &str, project: Option<&Project>) {
+        if let Some(listings) = self.listings.write().unwrap().as_mut() {
+            match project {
+                Some(project) => listings.insert(name.to_string(), project.into()),
+                None => listings.remove(name),
+            };
+        }
+    }
+
+    /// Every project in the repository ordered by name
+    pub fn list(&self) -> Result<Vec<Project>, Error> {
+        let mut projects = Vec::with_capacity(self.tree.len());
+        for entry in self.tree.iter() {
+            let (_, value) = entry?;
+            projects.push(serde_json::from_slice(&value)?);
+        }
+        Ok(projects)
+    }
+}
+
+/// Imports the project.json files written by older versions and removes them
+fn import_project_files(store: &ProjectStore, path: &Path) -> Result<(), Error> {
+    let mut files = Vec::new();
+    if path.exists() {
+        find_project_files(path, &mut files)?;
+    }
+    let mut imported = Vec::with_capacity(files.len());
+    for file in files {
+        match serde_json::from_reader::<_, Project>(std::fs::File::open(&file)?) {
+            Ok(project) => {
+                store.insert(&project)?;
+                imported.push(file);
+            }
+            Err(error) => warn!("Failed to import {}: {error}", file.display()),
+        }
+    }
+    store.tree.flush()?;
+    for file in imported.iter() {
+        std::fs::remove_file(file)?;
+    }
+    if !imported.is_empty() {
+        info!(
+            "Imported {} projects from {}",
+            imported.len(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn find_project_files(folder: &Path, projects: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in std::fs::read_dir(folder)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            find_project_files(&path, projects)?;
+        } else if entry.file_name() == "project.json" {
+            projects.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,277 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::ErrorKind;
+use std::path::Path;
+
+use actix_web::HttpResponse;
+use handlebars::Handlebars;
+use serde::Serialize;
+
+use crate::html::find_links;
+use crate::repository::Repository;
+use crate::search::{parse_javadoc, JavadocFiles, SearchKind};
+use crate::site::render_page;
+use crate::{Error, SiteSettings};
+
+/// Lists the packages of modern javadoc. Older javadoc calls it package-list
+static ELEMENT_LISTS: [&str; 2] = ["element-list", "package-list"];
+/// Lists the classes of javadoc that has no search index
+static CLASS_LISTS: [&str; 3] = [
+    "allclasses-index.html",
+    "allclasses-noframe.html",
+    "allclasses-frame.html",
+];
+static DEPRECATED_LIST: &str = "deprecated-list.html";
+
+/// The packages, types and members of a javadoc. Keyed by qualified name with the path inside the javadoc as value
+#[derive(Debug, Default)]
+pub struct ApiModel {
+    pub packages: BTreeMap<String, String>,
+    pub types: BTreeMap<String, String>,
+    pub members: BTreeMap<String, String>,
+    /// Paths that are linked from deprecated-list.html
+    pub deprecated: HashSet<String>,
+}
+
+impl ApiModel {
+    /// Uses the search index. Falls back to the element-list and the class lists for javadoc without one.
+    ///
+    /// Blocking. Async code uses [ApiModel::load_blocking]
+    pub fn load(javadoc: &Path) -> Result<Self, Error> {
+        let mut model = ApiModel::default();
+        for entry in parse_javadoc(javadoc)? {
+            let map = match entry.kind {
+                SearchKind::Package => &mut model.packages,
+                SearchKind::Type => &mut model.types,
+                SearchKind::Member => &mut model.members,
+            };
+            map.insert(entry.qualified_name(), entry.path);
+        }
+
+        let files = JavadocFiles::open(javadoc)?;
+        if model.packages.is_empty() {
+            for list in ELEMENT_LISTS {
+                if let Some(content) = files.read(list)? {
+                    model.packages.extend(
+                        content
+                            .lines()
+                            .map(str::trim)
+                            // Modules are listed as module:{name}
+                            .filter(|line| !line.is_empty() && !line.starts_with("module:"))
+                            .map(|package| {
+                                let path =
+                                    format!("{}/package-summary.html", package.replace('.', "/"));
+                                (package.to_string(), path)
+                            }),
+                    );
+                    break;
+                }
+            }
+        }
+        if model.types.is_empty() {
+            for list in CLASS_LISTS {
+                if let Some(content) = files.read(list)? {
+                    for link in find_links(content.as_bytes())? {
+                        if let Some(name) = type_name(&link) {
+                            model.types.insert(name, clean_link(&link).to_string());
+                        }
+                    }
+                    break;
+                }
+            }
+        }
+        if let Some(content) = files.read(DEPRECATED_LIST)? {
+            model.deprecated = find_links(content.as_bytes())?
+                .iter()
+                .map(|link| clean_link(link).to_string())
+                .collect();
+        }
+        Ok(model)
+    }
+
+    /// [ApiModel::load] on the blocking thread pool
+    pub async fn load_blocking(javadoc: &Path) -> Result<Self, Error> {
+        let javadoc = javadoc.to_path_buf();
+        tokio::task::spawn_blocking(move || ApiModel::load(&javadoc))
+            .await
+            .map_err(|error| std::io::Error::new(ErrorKind::Other, error))?
+    }
+}
+
+fn clean_link(link: &str) -> &str {
+    link.trim_start_matches("./")
+}
+
+/// `com/example/Foo.Bar.html` is the type `com.example.Foo.Bar`
+fn type_name(link: &str) -> Option<String> {
+    let link = clean_link(link);
+    if link.contains('#') || link.contains(':') {
+        return None;
+    }
+    let path = link.strip_suffix(".html")?;
+    let (package, name) = path.rsplit_once('/')?;
+    // Skip pages like package-summary.html
+    if name.contains('-') || !name.starts_with(|c: char| c.is_uppercase()) {
+        return None;
+    }
+    Some(format!("{}.{name}", package.replace('/', ".")))
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffEntry {
+    pub name: String,
+    pub url: String,
+}
+
+/// Added and removed elements. Deprecated are the elements in both versions that became deprecated
+#[derive(Debug, Default, Serialize)]
+pub struct Changes {
+    pub added: Vec<DiffEntry>,
+    pub removed: Vec<DiffEntry>,
+    pub deprecated: Vec<DiffEntry>,
+}
+
+impl Changes {
+    fn compare(
+        from: &BTreeMap<String, String>,
+        to: &BTreeMap<String, String>,
+        from_deprecated: &HashSet<String>,
+        to_deprecated: &HashSet<String>,
+        from_url: impl Fn(&str) -> String,
+        to_url: impl Fn(&str) -> String,
+    ) -> Self {
+        let mut changes = Changes::default();
+        for (name, path) in to.iter() {
+            match from.get(name) {
+                None => changes.added.push(DiffEntry {
+                    name: name.clone(),
+                    url: to_url(path),
+                }),
+                Some(old_path) => {
+                    if to_deprecated.contains(path) && !from_deprecated.contains(old_path) {
+                        changes.deprecated.push(DiffEntry {
+                            name: name.clone(),
+                            url: to_url(path),
+                        });
+                    }
+                }
+            }
+        }
+        for (name, path) in from.iter() {
+            if !to.contains_key(name) {
+                changes.removed.push(DiffEntry {
+                    name: name.clone(),
+                    url: from_url(path),
+                });
+            }
+        }
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.deprecated.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiDiff {
+    pub repository: String,
+    pub project: String,
+    pub from: String,
+    pub to: String,
+    pub packages: Changes,
+    pub types: Changes,
+    pub members: Changes,
+    pub unchanged: bool,
+}
+
+impl ApiDiff {
+    pub fn new(
+        repository: &Repository,
+        project: &str,
+        (from, from_model): (&str, &ApiModel),
+        (to, to_model): (&str, &ApiModel),
+    ) -> Self {
+        let from_url = |path: &str| repository.javadoc_url(project, from, path);
+        let to_url = |path: &str| repository.javadoc_url(project, to, path);
+        let compare = |from_map, to_map| {
+            Changes::compare(
+                from_map,
+                to_map,
+                &from_model.deprecated,
+                &to_model.deprecated,
+                from_url,
+                to_url,
+            )
+        };
+        let packages = compare(&from_model.packages, &to_model.packages);
+        let types = compare(&from_model.types, &to_model.types);
+        let mut members = compare(&from_model.members, &to_model.members);
+        // Members of added and removed types would only repeat the type
+        let changed_types: HashSet<&str> = types
+            .added
+            .iter()
+            .chain(types.removed.iter())
+            .map(|entry| entry.name.as_str())
+            .collect();
+        let changed_type = |entry: &DiffEntry| {
+            entry
+                .name
+                .split_once('#')
+                .map(|(container, _)| changed_types.contains(container))
+                .unwrap_or(false)
+        };
+        members.added.retain(|entry| !changed_type(entry));
+        members.removed.retain(|entry| !changed_type(entry));
+
+        let unchanged = packages.is_empty() && types.is_empty() && members.is_empty();
+        ApiDiff {
+            repository: repository.name.clone(),
+            project: project.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+            packages,
+            types,
+            members,
+            unchanged,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DiffRequest {
+    pub project: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// Compares two built versions of a project. Shared between the multi and single repository modes
+pub async fn serve_diff(
+    handlebars: &Handlebars<'_>,
+    site: &SiteSettings,
+    repository: &Repository,
+    request: DiffRequest,
+) -> actix_web::Result<HttpResponse> {
+    let project = repository
+        .get_project(&request.project)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Project not found"))?;
+    let mut models = Vec::with_capacity(2);
+    for version in [&request.from, &request.to] {
+        let (name, value) = project
+            .resolve_version(version)
+            .ok_or_else(|| actix_web::error::ErrorNotFound(format!("{version} is not built")))?;
+        let path = value
+            .path()
+            .ok_or_else(|| actix_web::error::ErrorNotFound(format!("{version} has no javadoc")))?;
+        models.push((name, ApiModel::load_blocking(path).await?));
+    }
+    let (to, to_model) = models.pop().unwrap();
+    let (from, from_model) = models.pop().unwrap();
+    let diff = ApiDiff::new(
+        repository,
+        &project.name,
+        (&from, &from_model),
+        (&to, &to_model),
+    );
+    render_page(handlebars, site, "site/diff.html", diff)
+}
@@ -0,0 +1,366 @@
+use std::sync::Arc;
+
+use actix_web::web::{Bytes, Data, Query};
+use actix_web::{post, HttpRequest, HttpResponse};
+use hmac::{Hmac, Mac};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+
+use crate::api::find_repository;
+use crate::project_processor::queue::BuildQueue;
+use crate::project_processor::ProjectRequest;
+use crate::repository::Repository;
+use crate::HookSettings;
+
+/// Sent by Artifactory with the secret token of the webhook
+static JFROG_AUTH: &str = "X-JFrog-Event-Auth";
+/// The hex HMAC-SHA1 of the body with the secret key of the Nexus webhook
+static NEXUS_SIGNATURE: &str = "X-Nexus-Webhook-Signature";
+static MAVEN_METADATA: &str = "maven-metadata.xml";
+
+/// Picks the repository if the notification does not name one this server knows
+#[derive(Debug, Deserialize)]
+pub struct HookQuery {
+    pub repository: Option<String>,
+}
+
+/// The deploy notifications that are understood
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum DeployEvent {
+    /// Nexus `rm:repository:component`.
+    /// The component is created before its snapshot maven-metadata.xml is updated so the asset event is the better choice for snapshots
+    NexusComponent {
+        #[serde(rename = "repositoryName")]
+        repository_name: String,
+        action: String,
+        component: NexusComponent,
+    },
+    /// Nexus `rm:repository:asset`
+    NexusAsset {
+        #[serde(rename = "repositoryName")]
+        repository_name: String,
+        action: String,
+        asset: NexusAsset,
+    },
+    /// Artifactory `artifact` events
+    Artifactory {
+        event_type: String,
+        data: ArtifactoryArtifact,
+    },
+    /// The path of a deployed file. Reposilite and anything else that reports files
+    File {
+        repository: Option<String>,
+        path: String,
+    },
+    /// The coordinates of a deployed artifact. For CI pipelines
+    Generic {
+        repository: Option<String>,
+        #[serde(alias = "groupId")]
+        group_id: String,
+        #[serde(alias = "artifactId")]
+        artifact_id: String,
+        version: Option<String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NexusComponent {
+    pub format: String,
+    pub group: String,
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NexusAsset {
+    pub format: String,
+    /// The path inside the repository
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArtifactoryArtifact {
+    pub repo_key: String,
+    /// The path inside the repository
+    pub path: String,
+}
+
+/// What a notification asks for
+#[derive(Debug)]
+pub struct Deploy {
+    /// The upstream repository
+    pub repository: Option<String>,
+    pub project: String,
+    /// None if only the list of versions changed
+    pub version: Option<String>,
+}
+
+impl DeployEvent {
+    /// The deployed artifact. Err with the reason if the notification is ignored
+    pub fn deploy(self) -> Result<Deploy, String> {
+        match self {
+            DeployEvent::NexusComponent {
+                repository_name,
+                action,
+                component,
+            } => {
+                nexus_action(&action)?;
+                maven_format(&component.format)?;
+                // Snapshot components carry the timestamped version
+                let version = snapshot_base(&component.version).unwrap_or(component.version);
+                Ok(Deploy {
+                    repository: Some(repository_name),
+                    project: format!("{}:{}", component.group, component.name),
+                    version: Some(version),
+                })
+            }
+            DeployEvent::NexusAsset {
+                repository_name,
+                action,
+                asset,
+            } => {
+                nexus_action(&action)?;
+                maven_format(&asset.format)?;
+                deployed_file(Some(repository_name), &asset.name)
+            }
+            DeployEvent::Artifactory { event_type, data } => {
+                if event_type != "deployed" {
+                    return Err(format!("{event_type} events are ignored"));
+                }
+                deployed_file(Some(data.repo_key), &data.path)
+            }
+            DeployEvent::File { repository, path } => deployed_file(repository, &path),
+            DeployEvent::Generic {
+                repository,
+                group_id,
+                artifact_id,
+                version,
+            } => Ok(Deploy {
+                repository,
+                project: format!("{group_id}:{artifact_id}"),
+                version: version.filter(|version| !version.is_empty() && version != "latest"),
+            }),
+        }
+    }
+}
+
+fn nexus_action(action: &str) -> Result<(), String> {
+    match action {
+        "CREATED" | "UPDATED" => Ok(()),
+        action => Err(format!("{action} events are ignored")),
+    }
+}
+
+fn maven_format(format: &str) -> Result<(), String> {
+    if format == "maven2" {
+        Ok(())
+    } else {
+        Err(format!("{format} is not a maven repository"))
+    }
+}
+
+/// `1.0-20220101.120000-1` becomes `1.0-SNAPSHOT`
+fn snapshot_base(version: &str) -> Option<String> {
+    let mut parts = version.rsplitn(3, '-');
+    let build = parts.next()?;
+    let timestamp = parts.next()?;
+    let base = parts.next()?;
+    let (date, time) = timestamp.split_once('.')?;
+    let digits = |value: &str, length: usize| {
+        value.len() == length && value.chars().all(|c| c.is_ascii_digit())
+    };
+    if digits(date, 8) && digits(time, 6) && !build.is_empty() && digits(build, build.len()) {
+        Some(format!("{base}-SNAPSHOT"))
+    } else {
+        None
+    }
+}
+
+/// Only the files that are uploaded once the deploy is complete are used.
+///
+/// The javadoc jar of a release, the maven-metadata.xml of a snapshot and the maven-metadata.xml of the artifact.
+/// Every other file of the deploy is ignored
+fn deployed_file(repository: Option<String>, path: &str) -> Result<Deploy, String> {
+    let segments: Vec<&str> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    let project = |folders: &[&str]| match folders.split_last() {
+        Some((artifact_id, group)) if !group.is_empty() => {
+            Ok(format!("{}:{artifact_id}", group.join(".")))
+        }
+        _ => Err(format!("{path} is not inside a maven artifact")),
+    };
+    match segments.split_last() {
+        Some((&file, folders)) if file == MAVEN_METADATA => match folders.split_last() {
+            Some((version, artifact)) if version.ends_with("-SNAPSHOT") => Ok(Deploy {
+                repository,
+                project: project(artifact)?,
+                version: Some(version.to_string()),
+            }),
+            _ => Ok(Deploy {
+                repository,
+                project: project(folders)?,
+                version: None,
+            }),
+        },
+        Some((&file, folders)) if file.ends_with("-javadoc.jar") => match folders.split_last() {
+            Some((version, artifact)) if !version.ends_with("-SNAPSHOT") => Ok(Deploy {
+                repository,
+                project: project(artifact)?,
+                version: Some(version.to_string()),
+            }),
+            _ => Err(format!(
+                "{path} is a snapshot. Its maven-metadata.xml is used"
+            )),
+        },
+        _ => Err(format!("{path} does not finish a deploy")),
+    }
+}
+
+/// The configured repository the notification is for.
+/// The query wins. Otherwise the upstream name has to match the name or the last part of the address
+fn target_repository(
+    repositories: &[Arc<Repository>],
+    query: &HookQuery,
+    upstream: Option<&str>,
+) -> actix_web::Result<Option<Arc<Repository>>> {
+    if let Some(name) = query.repository.as_ref() {
+        return find_repository(repositories, name).map(Some);
+    }
+    match upstream {
+        Some(upstream) => Ok(repositories
+            .iter()
+            .find(|repository| {
+                repository.name == upstream
+                    || repository.address.trim_end_matches('/').rsplit('/').next() == Some(upstream)
+            })
+            .cloned()),
+        None if repositories.len() == 1 => Ok(repositories.first().cloned()),
+        None => Err(actix_web::error::ErrorBadRequest(
+            "The repository is unknown. Add ?repository= to the hook url",
+        )),
+    }
+}
+
+/// Whether or not the request carries the token. Every way the supported servers send it is accepted
+fn authorized(request: &HttpRequest, body: &[u8], token: &str) -> bool {
+    let header = |name: &str| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    if let Some(bearer) = header("Authorization").and_then(|value| value.strip_prefix("Bearer ")) {
+        return constant_time_eq(bearer.as_bytes(), token.as_bytes());
+    }
+    if let Some(secret) = header(JFROG_AUTH) {
+        return constant_time_eq(secret.as_bytes(), token.as_bytes());
+    }
+    if let Some(signature) = header(NEXUS_SIGNATURE) {
+        let signature = match hex::decode(signature) {
+            Ok(signature) => signature,
+            Err(_) => return false,
+        };
+        let mut mac = match Hmac::<Sha1>::new_from_slice(token.as_bytes()) {
+            Ok(mac) => mac,
+            Err(_) => return false,
+        };
+        mac.update(body);
+        return mac.verify_slice(&signature).is_ok();
+    }
+    false
+}
+
+/// Fails unless the hook token is configured and the request carries it
+pub fn check_token(
+    request: &HttpRequest,
+    body: &[u8],
+    hooks: &HookSettings,
+) -> actix_web::Result<()> {
+    let token = hooks
+        .token
+        .as_ref()
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Hooks are not enabled"))?
+        .resolve()?;
+    if !authorized(request, body, &token) {
+        return Err(actix_web::error::ErrorUnauthorized("Invalid hook token"));
+    }
+    Ok(())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |result, (a, b)| result | (a ^ b)) == 0
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum HookResult {
+    Queued {
+        repository: String,
+        project: String,
+        version: Option<String>,
+        /// False if the same request was already pending or running
+        queued: bool,
+    },
+    Ignored {
+        reason: String,
+    },
+}
+
+/// Queues the deployed artifact. A deployed version is rebuilt even if the cache rules say it is fresh
+#[post("/hooks/deploy")]
+pub async fn deploy(
+    request: HttpRequest,
+    body: Bytes,
+    query: Query<HookQuery>,
+    hooks: Data<HookSettings>,
+    queue: Data<BuildQueue>,
+    repositories: Data<Vec<Arc<Repository>>>,
+) -> actix_web::Result<HttpResponse> {
+    check_token(&request, &body, &hooks)?;
+    let event: DeployEvent = serde_json::from_slice(&body).map_err(|error| {
+        actix_web::error::ErrorBadRequest(format!("Unsupported deploy notification: {error}"))
+    })?;
+    let deploy = match event.deploy() {
+        Ok(deploy) => deploy,
+        Err(reason) => {
+            debug!("Ignoring deploy notification: {reason}");
+            return Ok(HttpResponse::Ok().json(HookResult::Ignored { reason }));
+        }
+    };
+    let repository = match target_repository(&repositories, &query, deploy.repository.as_deref())? {
+        Some(repository) => repository,
+        None => {
+            return Ok(HttpResponse::Ok().json(HookResult::Ignored {
+                reason: format!(
+                    "{} is not served here. Add ?repository= to the hook url",
+                    deploy.repository.unwrap_or_default()
+                ),
+            }))
+        }
+    };
+    info!(
+        "Deploy of {} {:?} to {}",
+        deploy.project, deploy.version, repository.name
+    );
+    // Without a version only the list of versions changed. The latest is built if it is new
+    let force = deploy.version.is_some();
+    let queued = queue
+        .send(ProjectRequest {
+            repository: repository.clone(),
+            project: deploy.project.clone(),
+            version: deploy.version.clone(),
+            force,
+        })
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to send request"))?;
+    Ok(HttpResponse::Accepted().json(HookResult::Queued {
+        repository: repository.name.clone(),
+        project: deploy.project,
+        version: deploy.version,
+        queued,
+    }))
+}
@@ -1,23 +1,134 @@
-use std::io::Write;
+use std::cmp::Reverse;
+
+use handlebars::Handlebars;
 use lol_html::errors::RewritingError;
 use lol_html::html_content::{ContentType, Element};
 use lol_html::{element, HtmlRewriter, Settings};
-use crate::Resources;
+use serde::Serialize;
+
+use crate::links::LinkResolver;
+use crate::project::{Project, Version};
+use crate::version::MavenVersion;
+use crate::{Error, SiteSettings};
+
+/// The data the `header/header.html` and `header/header.css` templates are rendered with
+#[derive(Debug, Serialize)]
+pub struct Header<'a> {
+    pub site: &'a SiteSettings,
+    pub package: HeaderPackage,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeaderPackage {
+    pub group_id: String,
+    pub artifact_id: String,
+    pub version: String,
+    pub versions: Vec<HeaderVersion>,
+    /// Relative link to the project page
+    pub project_url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HeaderVersion {
+    pub name: String,
+    /// Relative link to the same file in this version
+    pub url: String,
+    pub current: bool,
+}
 
+impl<'a> Header<'a> {
+    /// Creates the header for a file of the project
+    ///
+    /// The links are relative to the file so they work no matter what the url prefix is.
+    pub fn new(site: &'a SiteSettings, project: &Project, version: &str, file: &str) -> Self {
+        let (group_id, artifact_id) = project
+            .name
+            .rsplit_once(':')
+            .unwrap_or(("", project.name.as_str()));
+        // One for every folder inside the javadoc and one for the version itself
+        let parent = "../".repeat(file.matches('/').count() + 1);
+
+        let mut versions: Vec<HeaderVersion> = Vec::new();
+        for (name, value) in project.versions.iter() {
+            let names: Vec<String> = match value {
+                Version::NoBuild { .. } => continue,
+                Version::Build { .. } => vec![name.clone()],
+                Version::BuildSnapshot { history, .. } => std::iter::once(name.clone())
+                    .chain(history.iter().map(|build| format!("{name}@{}", build.id)))
+                    .collect(),
+            };
+            versions.extend(names.into_iter().map(|name| HeaderVersion {
+                url: format!("{parent}{name}/{file}"),
+                current: name == version,
+                name,
+            }));
+        }
+        // Newest first. Kept snapshot builds follow their snapshot
+        versions.sort_by_cached_key(|version| {
+            let (base, build) = match version.name.split_once('@') {
+                Some((base, build)) => (base, Some(build.to_string())),
+                None => (version.name.as_str(), None),
+            };
+            (Reverse(MavenVersion::parse(base)), build.is_some(), Reverse(build))
+        });
+        Header {
+            site,
+            package: HeaderPackage {
+                group_id: group_id.to_string(),
+                artifact_id: artifact_id.to_string(),
+                version: version.to_string(),
+                versions,
+                project_url: parent,
+            },
+        }
+    }
+
+    pub fn render(&self, handlebars: &Handlebars<'_>) -> Result<String, Error> {
+        handlebars
+            .render("header/header.html", self)
+            .map_err(Error::from)
+    }
+
+    /// The css of the header with the branding applied
+    pub fn render_css(&self, handlebars: &Handlebars<'_>) -> Result<String, Error> {
+        handlebars
+            .render("header/header.css", self)
+            .map_err(Error::from)
+    }
+}
+
+/// Injects the rendered header and its css into a javadoc page.
+/// Links into the javadoc of other artifacts are pointed at this server
 pub fn rewrite_html(
     html: &[u8],
+    header: &str,
+    css: &str,
+    links: &LinkResolver<'_>,
 ) -> Result<Vec<u8>, RewritingError> {
-    let header = Resources::get("header/header.html").unwrap().data;
-    let css = Resources::get("header/header.css").unwrap().data;
-
+    let head_handler = |head: &mut Element| {
+        head.append(&format!("<style>{css}</style>"), ContentType::Html);
+        Ok(())
+    };
     let body_handler = |body: &mut Element| {
-        body.prepend(String::from_utf8_lossy(header.as_ref()).as_ref(), ContentType::Html);
+        body.prepend(header, ContentType::Html);
+        Ok(())
+    };
+    let link_handler = |link: &mut Element| {
+        if let Some(local) = link
+            .get_attribute("href")
+            .and_then(|href| links.resolve(&href))
+        {
+            link.set_attribute("href", &local)?;
+        }
         Ok(())
     };
 
     let settings = Settings {
         element_content_handlers: vec![
+            element!("head", head_handler),
             element!("body", body_handler),
+            element!("a[href]", link_handler),
         ],
         ..Settings::default()
     };
@@ -28,11 +139,25 @@ pub fn rewrite_html(
     });
 
     writer.write(html)?;
-    writer.write(b"<style>")?;
-    writer.write(css.as_ref())?;
-    writer.write(b"</style>")?;
     writer.end()?;
 
-
     Ok(buffer)
-}
\ No newline at end of file
+}
+
+/// The href of every link in the page
+pub fn find_links(html: &[u8]) -> Result<Vec<String>, RewritingError> {
+    let mut links = Vec::new();
+    let settings = Settings {
+        element_content_handlers: vec![element!("a[href]", |link| {
+            if let Some(href) = link.get_attribute("href") {
+                links.push(href);
+            }
+            Ok(())
+        })],
+        ..Settings::default()
+    };
+    let mut reader = HtmlRewriter::new(settings, |_: &[u8]| {});
+    reader.write(html)?;
+    reader.end()?;
+    Ok(links)
+}
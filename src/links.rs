@@ -0,0 +1,169 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use reqwest::Url;
+
+use crate::pom::Artifact;
+use crate::project::Version;
+use crate::repository::Repository;
+
+/// Hosts that serve the javadoc of maven artifacts under `{groupId}/{artifactId}/{version}/`
+static JAVADOC_IO: [&str; 2] = ["javadoc.io", "www.javadoc.io"];
+static STATIC_JAVADOC_IO: &str = "static.javadoc.io";
+
+/// A dependency that links can point at
+struct LinkedArtifact {
+    project: String,
+    version: String,
+    /// The packages of the built versions. Empty if none are built yet
+    packages: Arc<HashSet<String>>,
+    group_id: String,
+}
+
+impl LinkedArtifact {
+    /// Whether or not the package belongs to the artifact.
+    /// Without a built version the package has to start with the group id, which is only
+    /// trusted for links to a known javadoc host
+    fn contains(&self, package: &str, javadoc_host: bool) -> bool {
+        if self.packages.is_empty() {
+            javadoc_host
+                && (package == self.group_id || package.starts_with(&format!("{}.", self.group_id)))
+        } else {
+            self.packages.contains(package)
+        }
+    }
+}
+
+/// Rewrites links into the javadoc of other maven artifacts to this server
+pub struct LinkResolver<'a> {
+    repository: &'a Repository,
+    /// The host of the maven repository, which also serves javadoc jars
+    repository_host: Option<String>,
+    dependencies: Vec<LinkedArtifact>,
+}
+
+impl<'a> LinkResolver<'a> {
+    pub fn new(repository: &'a Repository, artifacts: Vec<Artifact>) -> Self {
+        let dependencies = artifacts
+            .into_iter()
+            .map(|artifact| LinkedArtifact {
+                packages: repository.search.packages(&artifact.project),
+                group_id: artifact
+                    .project
+                    .split_once(':')
+                    .map(|(group_id, _)| group_id.to_string())
+                    .unwrap_or_default(),
+                version: artifact.version.unwrap_or_else(|| "latest".to_string()),
+                project: artifact.project,
+            })
+            .collect();
+        let repository_host = Url::parse(&repository.address)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+        Self {
+            repository,
+            repository_host,
+            dependencies,
+        }
+    }
+
+    /// Uses the dependencies of the version's POM. Without a POM only javadoc.io links are resolved
+    pub fn for_version(repository: &'a Repository, version: &Version) -> Self {
+        let artifacts = version
+            .pom()
+            .map(|pom| pom.dependencies.clone())
+            .unwrap_or_default();
+        Self::new(repository, artifacts)
+    }
+
+    /// The url on this server. None if the link does not point into the javadoc of a maven artifact
+    pub fn resolve(&self, href: &str) -> Option<String> {
+        if !href.starts_with("http://") && !href.starts_with("https://") {
+            return None;
+        }
+        let url = Url::parse(href).ok()?;
+        let segments: Vec<&str> = url.path_segments()?.collect();
+        let host = url.host_str()?;
+        let (project, version, file) = javadoc_io(host, &segments)
+            .or_else(|| self.dependency_link(&segments, self.is_javadoc_host(host)))?;
+        let mut local = self.repository.javadoc_url(&project, &version, &file);
+        if let Some(query) = url.query() {
+            local.push('?');
+            local.push_str(query);
+        }
+        if let Some(fragment) = url.fragment() {
+            local.push('#');
+            local.push_str(fragment);
+        }
+        Some(local)
+    }
+
+    /// javadoc.io or the configured maven repository
+    fn is_javadoc_host(&self, host: &str) -> bool {
+        JAVADOC_IO.contains(&host)
+            || host == STATIC_JAVADOC_IO
+            || self.repository_host.as_deref() == Some(host)
+    }
+
+    /// Finds the dependency that contains the package of the linked page.
+    /// The longest package wins so `/apidocs/com/example/Foo.html` prefers `com.example` over `example`.
+    /// Other hosts only match packages of built dependencies so unrelated sites are left alone
+    fn dependency_link(
+        &self,
+        segments: &[&str],
+        javadoc_host: bool,
+    ) -> Option<(String, String, String)> {
+        let (page, folders) = segments.split_last()?;
+        if !page.ends_with(".html") {
+            return None;
+        }
+        for start in 0..folders.len() {
+            let package = folders[start..].join(".");
+            // Built dependencies know their packages. They win over the group id
+            let dependency = self
+                .dependencies
+                .iter()
+                .filter(|dependency| dependency.contains(&package, javadoc_host))
+                .max_by_key(|dependency| {
+                    (!dependency.packages.is_empty(), dependency.group_id.len())
+                });
+            if let Some(dependency) = dependency {
+                return Some((
+                    dependency.project.clone(),
+                    dependency.version.clone(),
+                    segments[start..].join("/"),
+                ));
+            }
+        }
+        None
+    }
+}
+
+/// `javadoc.io/doc/{groupId}/{artifactId}/{version}/{file}` and the static variants
+fn javadoc_io(host: &str, segments: &[&str]) -> Option<(String, String, String)> {
+    let segments = if JAVADOC_IO.contains(&host) {
+        match segments.split_first() {
+            Some((&"doc" | &"static", rest)) => rest,
+            _ => return None,
+        }
+    } else if host == STATIC_JAVADOC_IO {
+        segments
+    } else {
+        return None;
+    };
+    match segments {
+        [group_id, artifact_id, rest @ ..] if !group_id.is_empty() && !artifact_id.is_empty() => {
+            let version = rest
+                .first()
+                .filter(|version| !version.is_empty())
+                .unwrap_or(&"latest");
+            let file = rest.get(1..).map(|file| file.join("/")).unwrap_or_default();
+            Some((
+                format!("{group_id}:{artifact_id}"),
+                version.to_string(),
+                file,
+            ))
+        }
+        _ => None,
+    }
+}
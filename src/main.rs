@@ -14,16 +14,31 @@ use rust_embed::RustEmbed;
 use serde::{Deserialize, Serialize};
 use this_actix_error::ActixError;
 use thiserror::Error;
-use crate::repository::CacheRules;
+use crate::cache_command::CacheCommand;
+use crate::prefetch::PrefetchArgs;
+use crate::repository::{CacheRules, RepositoryAuth, Secret, Storage, Verification};
 
+pub(crate) mod api;
+pub(crate) mod cache;
+pub(crate) mod cache_command;
+pub(crate) mod database;
+pub(crate) mod diff;
+pub(crate) mod hooks;
 pub(crate) mod multi;
+pub(crate) mod pom;
+pub(crate) mod prefetch;
 pub(crate) mod project;
 pub(crate) mod project_processor;
+pub(crate) mod report;
 pub(crate) mod repository;
 pub(crate) mod single;
+pub(crate) mod version;
 pub(crate) mod web;
 pub(crate) mod zip;
 pub(crate) mod html;
+pub(crate) mod links;
+pub(crate) mod metrics;
+pub(crate) mod search;
 pub(crate) mod site;
 
 static CONFIG: &str = "my_javadoc.toml";
@@ -43,27 +58,127 @@ pub struct Config {
     pub single_repo: bool,
     pub repositories: HashMap<String, ConfigRepository>,
     pub log_location: Option<PathBuf>,
+    /// Amount of projects that can be processed at the same time
+    #[serde(default = "default_workers")]
+    pub workers: usize,
+    /// Max disk space of the whole cache in megabytes
+    #[serde(default)]
+    pub max_cache_size: Option<u64>,
+    /// Minutes between checking the cache quotas
+    #[serde(default = "default_sweep_interval")]
+    pub sweep_interval: u64,
+    /// Shown on the site pages
+    #[serde(default)]
+    pub site: SiteSettings,
+    /// The webhooks upstream repositories call on deploy
+    #[serde(default)]
+    pub hooks: HookSettings,
     #[cfg(feature = "ssl")]
     pub ssl_private_key: Option<PathBuf>,
     #[cfg(feature = "ssl")]
     pub ssl_cert_key: Option<PathBuf>,
 }
 
+fn default_workers() -> usize {
+    2
+}
+
+fn default_sweep_interval() -> u64 {
+    60
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConfigRepository {
     pub address: String,
     /// Does this repository allow redeploy of artifacts
-    /// If true every 24 hours a query to update the cache is made
+    /// If true releases are checked again every `cache.time_til_update` hours
     #[serde(default)]
     pub allows_redeploy: bool,
     #[serde(default)]
-    pub cache: CacheRules
+    pub cache: CacheRules,
+    /// Extract the javadoc jars or serve straight out of them
+    #[serde(default)]
+    pub storage: Storage,
+    /// Checksum and signature checks for downloaded jars
+    #[serde(default)]
+    pub verification: Verification,
+    /// Credentials for the repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<RepositoryAuth>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HookSettings {
+    /// Required by `/hooks/deploy`. The endpoint is disabled without it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<Secret>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SiteSettings {
     pub title: String,
+    #[serde(default)]
     pub description: String,
+    /// Files in this directory replace the embedded template with the same path. Like `site/index.html` or `header/header.css`.
+    /// Files in its `assets` folder are served under `/assets/`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub templates: Option<PathBuf>,
+    #[serde(default)]
+    pub branding: Branding,
+}
+
+impl Default for SiteSettings {
+    fn default() -> Self {
+        Self {
+            title: "My Javadoc".to_string(),
+            description: "Javadoc for the artifacts of our Maven repositories".to_string(),
+            templates: None,
+            branding: Branding::default(),
+        }
+    }
+}
+
+/// The look of the site and the header injected into the javadoc. Colors are any css color
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Branding {
+    /// Shown next to the title. `/assets/logo.png` is the logo.png in the assets of the templates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub favicon: Option<String>,
+    /// The background of the header
+    #[serde(default = "default_primary_color")]
+    pub primary_color: String,
+    /// Links and hovered buttons
+    #[serde(default = "default_accent_color")]
+    pub accent_color: String,
+    /// The text on the primary color
+    #[serde(default = "default_text_color")]
+    pub text_color: String,
+}
+
+fn default_primary_color() -> String {
+    "#343a40".to_string()
+}
+
+fn default_accent_color() -> String {
+    "#555".to_string()
+}
+
+fn default_text_color() -> String {
+    "white".to_string()
+}
+
+impl Default for Branding {
+    fn default() -> Self {
+        Self {
+            logo: None,
+            favicon: None,
+            primary_color: default_primary_color(),
+            accent_color: default_accent_color(),
+            text_color: default_text_color(),
+        }
+    }
 }
 
 #[derive(Parser)]
@@ -77,6 +192,17 @@ pub struct MyJavaDoc {
 enum MyJavaDocSubCommand {
     /// Run the server
     Run,
+    /// Build javadoc ahead of time to warm the cache
+    ///
+    /// The server must be stopped. A running server builds a version on `POST /api/{repository}/{project}?version=`
+    Prefetch(PrefetchArgs),
+    /// Inspect and manage the cache
+    ///
+    /// The server must be stopped. A running server lists its projects on `/api/{repository}/projects`
+    Cache {
+        #[clap(subcommand)]
+        command: CacheCommand,
+    },
 }
 
 #[derive(Debug, Error, ActixError)]
@@ -93,6 +219,37 @@ pub enum Error {
     XMLError(#[from] DeError),
     #[error(transparent)]
     ZipError(#[from] ZipError),
+    #[error(transparent)]
+    Database(#[from] sled::Error),
+    #[error("{} is in use by a running server. Stop the server first or use the HTTP API", .0.display())]
+    DatabaseLocked(PathBuf),
+    #[error("Checksum mismatch for {url}. Expected {expected} got {actual}")]
+    ChecksumMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("{0}")]
+    Unverified(String),
+    #[error("No javadoc available for {0}")]
+    NoJavadoc(String),
+    #[error("Environment variable {0} is not set")]
+    MissingEnv(String),
+    #[error(transparent)]
+    RenderError(#[from] handlebars::RenderError),
+    #[error(transparent)]
+    TemplateError(#[from] handlebars::TemplateError),
+    #[error(transparent)]
+    RewritingError(#[from] lol_html::errors::RewritingError),
+}
+
+/// A locked database is a mistake of the user. It gets a message instead of an error dump
+fn command_error(error: Error) -> std::io::Error {
+    if let Error::DatabaseLocked(_) = error {
+        println!("{error}");
+        exit(1);
+    }
+    std::io::Error::new(ErrorKind::Other, error)
 }
 
 #[tokio::main]
@@ -108,6 +265,11 @@ async fn main() -> std::io::Result<()> {
             cache: current_dir()?.join("cache"),
             single_repo: false,
             log_location: None,
+            workers: default_workers(),
+            max_cache_size: None,
+            sweep_interval: default_sweep_interval(),
+            site: SiteSettings::default(),
+            hooks: HookSettings::default(),
         };
         let config = toml::to_string_pretty(&config).unwrap();
         std::fs::write(&main_config, config)?;
@@ -115,8 +277,8 @@ async fn main() -> std::io::Result<()> {
     let init_settings: Config = toml::from_str(&read_to_string(&main_config)?)
         .map_err(|v| std::io::Error::new(ErrorKind::InvalidData, v))?;
     if init_settings.single_repo {
-        if init_settings.repositories.len() == 1 {
-            println!("Single Repo is set to true but more than one repo is set");
+        if init_settings.repositories.len() != 1 {
+            println!("Single Repo is set to true but there is not exactly one repo set");
             exit(1);
         }
     }
@@ -128,14 +290,30 @@ async fn main() -> std::io::Result<()> {
             current_dir().unwrap().join("logs").as_os_str(),
         );
     }
+    let logger: nitro_log::config::Config =
+        serde_json::from_slice(Resources::get("log.json").unwrap().data.as_ref()).unwrap();
+    nitro_log::NitroLogger::load(logger, LoggerBuilders::default()).unwrap();
     match args.command {
         MyJavaDocSubCommand::Run => {
-            let logger: nitro_log::config::Config =
-                serde_json::from_slice(Resources::get("log.json").unwrap().data.as_ref()).unwrap();
-            nitro_log::NitroLogger::load(logger, LoggerBuilders::default()).unwrap();
             info!("Starting server");
             web::start(init_settings).await?;
         }
+        MyJavaDocSubCommand::Prefetch(prefetch) => {
+            let success = prefetch::prefetch(init_settings, prefetch)
+                .await
+                .map_err(command_error)?;
+            if !success {
+                exit(1);
+            }
+        }
+        MyJavaDocSubCommand::Cache { command } => {
+            let success = cache_command::cache_command(init_settings, command)
+                .await
+                .map_err(command_error)?;
+            if !success {
+                exit(1);
+            }
+        }
     }
     Ok(())
 }
@@ -0,0 +1,104 @@
+use actix_web::{get, HttpResponse};
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder,
+};
+
+/// Javadoc requests by repository and response status
+pub static REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "my_javadoc_requests_total",
+        "Javadoc requests",
+        &["repository", "status"]
+    )
+    .unwrap()
+});
+
+pub static REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "my_javadoc_request_duration_seconds",
+        "Time taken to respond to a javadoc request",
+        &["repository"]
+    )
+    .unwrap()
+});
+
+/// `hit` if the javadoc was served. `miss` if it had to be queued
+pub static CACHE: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "my_javadoc_cache_total",
+        "Cache hits and misses of javadoc requests",
+        &["repository", "result"]
+    )
+    .unwrap()
+});
+
+pub static QUEUE_DEPTH: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "my_javadoc_queue_depth",
+        "Requests waiting to be processed"
+    )
+    .unwrap()
+});
+
+pub static JOB_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "my_javadoc_job_duration_seconds",
+        "Time taken to process a project request",
+        &["repository", "result"],
+        vec![0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0]
+    )
+    .unwrap()
+});
+
+/// Responses from the upstream repositories by status code
+pub static UPSTREAM_RESPONSES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "my_javadoc_upstream_responses_total",
+        "Responses from upstream repositories",
+        &["repository", "status"]
+    )
+    .unwrap()
+});
+
+pub static DOWNLOADED_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "my_javadoc_downloaded_bytes_total",
+        "Bytes of javadoc jars downloaded",
+        &["repository"]
+    )
+    .unwrap()
+});
+
+pub static EXTRACTED_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "my_javadoc_extracted_bytes_total",
+        "Bytes extracted out of javadoc jars",
+        &["repository"]
+    )
+    .unwrap()
+});
+
+/// Updated by the cache sweeper
+pub static CACHE_SIZE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "my_javadoc_cache_size_bytes",
+        "Disk space used by built javadoc",
+        &["repository"]
+    )
+    .unwrap()
+});
+
+/// Prometheus text format
+#[get("/metrics")]
+pub async fn metrics() -> HttpResponse {
+    let mut buffer = Vec::new();
+    if let Err(error) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        return HttpResponse::InternalServerError().body(error.to_string());
+    }
+    HttpResponse::Ok()
+        .content_type(TextEncoder::new().format_type())
+        .body(buffer)
+}
@@ -1,15 +1,29 @@
+use std::borrow::Cow;
 use std::ops::Add;
 use std::sync::Arc;
+use std::time::Instant;
 
 use actix_web::web::ServiceConfig;
 use actix_web::{web, HttpResponse};
-use actix_web::http::header::{CACHE_CONTROL, EXPIRES};
+use actix_web::http::header::{CACHE_CONTROL, EXPIRES, LOCATION};
 use chrono::{Duration, Utc};
+use handlebars::Handlebars;
 use serde::Deserialize;
-use tokio::sync::mpsc::Sender;
+use serde_json::json;
 
+use crate::api::find_repository;
+use crate::diff::{serve_diff, DiffRequest};
+use crate::report::serve_report;
+use crate::site::{serve_project, serve_repository, ProjectQuery, SitePage};
+use crate::html::Header;
+use crate::project_processor::jobs::Job;
+use crate::project_processor::queue::{BuildQueue, QueueEntry};
 use crate::project_processor::ProjectRequest;
+use crate::metrics;
+use crate::project::{Project, Version};
 use crate::repository::Repository;
+use crate::version::VersionAlias;
+use crate::SiteSettings;
 
 #[derive(Debug, Deserialize)]
 pub struct Request {
@@ -19,19 +33,132 @@ pub struct Request {
     pub file: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct DiffPath {
+    pub repository: String,
+    pub project: String,
+    pub from: String,
+    pub to: String,
+}
+
 pub fn register_web(service: &mut ServiceConfig) {
-    service.service(
-        web::resource([
-            "/{repository}/{project}/{version}/{file:.*}",
-            "/{repository}/{project}/{version}/",
-        ])
-        .name("get_javadoc")
-        .route(web::get().to(get_javadoc)),
-    );
+    // The diff and the report have to come first. The javadoc route would match them
+    service
+        .service(
+            web::resource("/{repository}/{project}/diff/{from}/{to}")
+                .name("get_diff")
+                .route(web::get().to(get_diff)),
+        )
+        .service(
+            web::resource("/{repository}/{project}/report/{version}")
+                .name("get_report")
+                .route(web::get().to(get_report)),
+        )
+        .service(
+            web::resource(["/{repository}", "/{repository}/"])
+                .name("get_repository")
+                .route(web::get().to(get_repository)),
+        )
+        .service(
+            web::resource(["/{repository}/{project}", "/{repository}/{project}/"])
+                .name("get_project")
+                .route(web::get().to(get_project)),
+        )
+        .service(
+            web::resource([
+                "/{repository}/{project}/{version}/{file:.*}",
+                "/{repository}/{project}/{version}/",
+            ])
+            .name("get_javadoc")
+            .route(web::get().to(get_javadoc)),
+        );
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportPath {
+    pub repository: String,
+    pub project: String,
+    pub version: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProjectPath {
+    pub repository: String,
+    pub project: String,
+}
+
+pub async fn get_repository(
+    handlebars: web::Data<Handlebars<'static>>,
+    site: web::Data<SiteSettings>,
+    repository: web::Path<String>,
+    repositories: web::Data<Vec<Arc<Repository>>>,
+) -> actix_web::Result<HttpResponse> {
+    let repository = find_repository(&repositories, &repository)?;
+    serve_repository(&handlebars, &site, &repository).await
+}
+
+pub async fn get_project(
+    requests: web::Data<BuildQueue>,
+    handlebars: web::Data<Handlebars<'static>>,
+    site: web::Data<SiteSettings>,
+    request: web::Path<ProjectPath>,
+    query: web::Query<ProjectQuery>,
+    repositories: web::Data<Vec<Arc<Repository>>>,
+) -> actix_web::Result<HttpResponse> {
+    let repository = find_repository(&repositories, &request.repository)?;
+    serve_project(
+        &handlebars,
+        &site,
+        &requests,
+        &repository,
+        &request.project,
+        query.into_inner(),
+    )
+    .await
+}
+
+pub async fn get_report(
+    handlebars: web::Data<Handlebars<'static>>,
+    site: web::Data<SiteSettings>,
+    request: web::Path<ReportPath>,
+    repositories: web::Data<Vec<Arc<Repository>>>,
+) -> actix_web::Result<HttpResponse> {
+    let repository = find_repository(&repositories, &request.repository)?;
+    serve_report(
+        &handlebars,
+        &site,
+        &repository,
+        &request.project,
+        &request.version,
+    )
+    .await
+}
+
+pub async fn get_diff(
+    handlebars: web::Data<Handlebars<'static>>,
+    site: web::Data<SiteSettings>,
+    request: web::Path<DiffPath>,
+    repositories: web::Data<Vec<Arc<Repository>>>,
+) -> actix_web::Result<HttpResponse> {
+    let request = request.into_inner();
+    let repository = find_repository(&repositories, &request.repository)?;
+    serve_diff(
+        &handlebars,
+        &site,
+        &repository,
+        DiffRequest {
+            project: request.project,
+            from: request.from,
+            to: request.to,
+        },
+    )
+    .await
 }
 
 pub async fn get_javadoc(
-    requests: web::Data<Sender<ProjectRequest>>,
+    requests: web::Data<BuildQueue>,
+    handlebars: web::Data<Handlebars<'static>>,
+    site: web::Data<SiteSettings>,
     request: web::Path<Request>,
     repositories: web::Data<Vec<Arc<Repository>>>,
 ) -> actix_web::Result<HttpResponse> {
@@ -41,59 +168,154 @@ pub async fn get_javadoc(
         .ok_or(actix_web::error::ErrorNotFound("Repository not found"))?
         .clone();
     let request = request.into_inner();
+    serve_javadoc(
+        &requests,
+        &handlebars,
+        &site,
+        repository,
+        JavadocRequest {
+            project: request.project,
+            version: request.version,
+            file: request.file,
+        },
+    )
+    .await
+}
+
+/// A request for a file inside of a project's javadoc. Independent of how the repository was resolved
+#[derive(Debug)]
+pub struct JavadocRequest {
+    pub project: String,
+    pub version: String,
+    pub file: Option<String>,
+}
+
+/// Loads the requested file or queues the project for building if it is not available.
+///
+/// Shared between the multi and single repository modes
+pub async fn serve_javadoc(
+    requests: &BuildQueue,
+    handlebars: &Handlebars<'_>,
+    site: &SiteSettings,
+    repository: Arc<Repository>,
+    request: JavadocRequest,
+) -> actix_web::Result<HttpResponse> {
+    let start = Instant::now();
+    let name = repository.name.clone();
+    let response = load_javadoc(requests, handlebars, site, repository, request).await;
+    let status = match &response {
+        Ok(response) => response.status(),
+        Err(error) => error.as_response_error().status_code(),
+    };
+    metrics::REQUESTS
+        .with_label_values(&[&name, status.as_str()])
+        .inc();
+    metrics::REQUEST_DURATION
+        .with_label_values(&[&name])
+        .observe(start.elapsed().as_secs_f64());
+    response
+}
+
+async fn load_javadoc(
+    requests: &BuildQueue,
+    handlebars: &Handlebars<'_>,
+    site: &SiteSettings,
+    repository: Arc<Repository>,
+    request: JavadocRequest,
+) -> actix_web::Result<HttpResponse> {
     if let Some(project) = repository.get_project(&request.project).await? {
         let (text, version) = if request.version.eq("latest") {
+            if project.should_update(&repository) {
+                // Look for a newer version while the current latest is served
+                requests
+                    .send(ProjectRequest {
+                        repository: repository.clone(),
+                        project: request.project.clone(),
+                        version: None,
+                        force: false,
+                    })
+                    .await
+                    .map_err(|_| {
+                        actix_web::error::ErrorInternalServerError("Failed to send request")
+                    })?;
+            }
             if let Some(v) = project.latest.as_ref() {
                 if let Some(x) = project.versions.get(v) {
-                    (v, x)
+                    (v, Cow::Borrowed(x))
                 } else {
-                    requests
-                        .send(ProjectRequest {
+                    return queue_build(
+                        requests,
+                        handlebars,
+                        site,
+                        ProjectRequest {
                             repository,
                             project: request.project,
                             version: Some(v.to_owned()),
-                        })
-                        .await
-                        .map_err(|_| {
-                            actix_web::error::ErrorInternalServerError("Failed to send request")
-                        })?;
-                    return Err(actix_web::error::ErrorNotFound("Version not found"));
+                            force: false,
+                        },
+                    )
+                    .await;
                 }
             } else {
                 return Err(actix_web::error::ErrorNotFound("No latest version found"));
             }
+        } else if request.version.contains('@') {
+            // An older build of a snapshot
+            match project.resolve_version(&request.version) {
+                Some((_, value)) => (&request.version, value),
+                None => {
+                    return Err(actix_web::error::ErrorNotFound(
+                        "Snapshot build not found",
+                    ))
+                }
+            }
         } else {
             if let Some(v) = project.versions.get(&request.version) {
-                (&request.version, v)
+                (&request.version, Cow::Borrowed(v))
             } else {
-                requests
-                    .send(ProjectRequest {
+                if let Some(response) =
+                    redirect_alias(requests, &repository, &project, &request).await?
+                {
+                    return Ok(response);
+                }
+                return queue_build(
+                    requests,
+                    handlebars,
+                    site,
+                    ProjectRequest {
                         repository,
                         project: request.project,
                         version: Some(request.version),
-                    })
-                    .await
-                    .map_err(|_| {
-                        actix_web::error::ErrorInternalServerError("Failed to send request")
-                    })?;
-
-                return Err(actix_web::error::ErrorNotFound("Version not found"));
+                        force: false,
+                    },
+                )
+                .await;
             }
         };
-        if version.should_be_sent_for_rebuilding(&repository) {
+        // Kept snapshot builds never change
+        if !text.contains('@') && version.should_be_sent_for_rebuilding(&repository) {
             requests
                 .send(ProjectRequest {
-                    repository,
+                    repository: repository.clone(),
                     project: request.project,
                     version: Some(text.clone()),
+                    force: false,
                 })
                 .await
                 .map_err(|_| {
                     actix_web::error::ErrorInternalServerError("Failed to send request")
                 })?;
         }
-        let option = version.load_file(request.file).await?;
+        if let Version::NoBuild { .. } = version.as_ref() {
+            return Err(not_available(&project.name, text));
+        }
+        let header = Header::new(site, &project, text, request.file.as_deref().unwrap_or(""));
+        let option = version.load_file(request.file, &repository, &header, handlebars).await?;
         if let Some(file) = option {
+            repository.access.touch(&project.name, text);
+            metrics::CACHE
+                .with_label_values(&[&repository.name, "hit"])
+                .inc();
             return Ok(HttpResponse::Ok()
                 .content_type(file.content_type)
                 .append_header((EXPIRES, project.last_updated.unwrap_or(Utc::now()).add(Duration::days(1)).to_rfc2822()))
@@ -102,14 +324,129 @@ pub async fn get_javadoc(
             return Err(actix_web::error::ErrorNotFound("File not found"));
         }
     } else {
-        requests
-            .send(ProjectRequest {
+        queue_build(
+            requests,
+            handlebars,
+            site,
+            ProjectRequest {
                 repository,
                 project: request.project,
                 version: None,
+                force: false,
+            },
+        )
+        .await
+    }
+}
+
+/// Redirects an alias like `release`, `2.x` or `[1.0,2.0)` to the newest version it matches.
+///
+/// None if the name is not an alias or is a version in the maven-metadata.xml
+async fn redirect_alias(
+    requests: &BuildQueue,
+    repository: &Arc<Repository>,
+    project: &Project,
+    request: &JavadocRequest,
+) -> actix_web::Result<Option<HttpResponse>> {
+    let alias = match VersionAlias::parse(&request.version) {
+        Some(alias) => alias,
+        None => return Ok(None),
+    };
+    let mut versions: Vec<String> = project.versions.keys().cloned().collect();
+    if let Ok(deploy_data) = project.get_deploy_data(repository).await {
+        if deploy_data
+            .versioning
+            .versions
+            .version
+            .contains(&request.version)
+        {
+            return Ok(None);
+        }
+        versions.extend(deploy_data.versioning.versions.version);
+    }
+    if project.should_update(repository) {
+        // Look for newer versions while the alias is resolved with the known ones
+        requests
+            .send(ProjectRequest {
+                repository: repository.clone(),
+                project: request.project.clone(),
+                version: None,
+                force: false,
             })
             .await
             .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to send request"))?;
-        return Err(actix_web::error::ErrorNotFound("Project not found"));
     }
+    let version = match alias.resolve(versions.iter()) {
+        Some(version) => version,
+        // `2` or `2.3` can be a version that is not known yet. It is built like any other version
+        None => return Ok(None),
+    };
+    let location = repository.javadoc_url(
+        &request.project,
+        version,
+        request.file.as_deref().unwrap_or(""),
+    );
+    // The alias moves with every release
+    Ok(Some(
+        HttpResponse::Found()
+            .append_header((LOCATION, location))
+            .append_header((CACHE_CONTROL, "no-cache"))
+            .finish(),
+    ))
+}
+
+fn not_available(project: &str, version: &str) -> actix_web::Error {
+    actix_web::error::ErrorNotFound(format!("No javadoc is available for {project} {version}"))
+}
+
+/// Queues the request and responds with a page that refreshes until the build is done.
+///
+/// If the same request failed recently the error is returned instead of trying again.
+/// If it finished without a javadoc the version is not available
+async fn queue_build(
+    requests: &BuildQueue,
+    handlebars: &Handlebars<'_>,
+    site: &SiteSettings,
+    request: ProjectRequest,
+) -> actix_web::Result<HttpResponse> {
+    metrics::CACHE
+        .with_label_values(&[&request.repository.name, "miss"])
+        .inc();
+    let entry = QueueEntry::from(&request);
+    let job = requests.job(&entry).await;
+    let now = Utc::now();
+    if let Some(Job::Failed { error, .. }) = job.as_ref().filter(|job| job.recently_failed(now)) {
+        return Err(actix_web::error::ErrorBadGateway(format!(
+            "Building the javadoc failed: {error}"
+        )));
+    }
+    // The build finished without storing the version. Queueing it again would loop
+    if job.filter(|job| job.recently_finished(now)).is_some() {
+        return Err(not_available(
+            &entry.project,
+            entry.version.as_deref().unwrap_or("latest"),
+        ));
+    }
+    requests
+        .send(request)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to send request"))?;
+    let job = requests.job(&entry).await;
+    let body = handlebars
+        .render(
+            "site/building.html",
+            &SitePage {
+                site,
+                page: json!({
+                    "repository": entry.repository,
+                    "project": entry.project,
+                    "version": entry.version.as_deref().unwrap_or("latest"),
+                    "job": job,
+                }),
+            },
+        )
+        .map_err(crate::Error::from)?;
+    Ok(HttpResponse::Accepted()
+        .content_type("text/html; charset=utf-8")
+        .body(body))
 }
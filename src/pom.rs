@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use log::debug;
+use maven_rs::quick_xml;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::repository::{project_to_path, Repository};
+use crate::Error;
+
+/// The parts of a pom.xml the server uses
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pom {
+    pub group_id: Option<String>,
+    pub artifact_id: Option<String>,
+    pub version: Option<String>,
+    pub parent: Option<Parent>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    #[serde(default)]
+    pub licenses: Licenses,
+    pub scm: Option<Scm>,
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+    #[serde(default)]
+    pub dependencies: Dependencies,
+    pub dependency_management: Option<DependencyManagement>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Parent {
+    /// Left out when it is inherited from the parent's parent
+    pub group_id: Option<String>,
+    pub version: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Licenses {
+    #[serde(default)]
+    pub license: Vec<License>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct License {
+    pub name: Option<String>,
+    pub url: Option<String>,
+}
+
+/// Where the source code is kept
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Scm {
+    pub url: Option<String>,
+    pub connection: Option<String>,
+    pub developer_connection: Option<String>,
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Dependencies {
+    #[serde(default)]
+    pub dependency: Vec<Dependency>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DependencyManagement {
+    #[serde(default)]
+    pub dependencies: Dependencies,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dependency {
+    /// Missing when it is inherited or managed by a BOM this POM does not include
+    pub group_id: Option<String>,
+    pub artifact_id: String,
+    pub version: Option<String>,
+    pub scope: Option<String>,
+}
+
+/// A dependency with its properties resolved
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Artifact {
+    /// `groupId:artifactId`
+    pub project: String,
+    /// None if the version is a range or could not be resolved
+    pub version: Option<String>,
+    /// None is the compile scope
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+/// What is kept of the POM with every built version
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PomMetadata {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    #[serde(default)]
+    pub licenses: Vec<License>,
+    pub scm: Option<Scm>,
+    #[serde(default)]
+    pub dependencies: Vec<Artifact>,
+}
+
+impl Pom {
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        quick_xml::de::from_str(text).map_err(Error::from)
+    }
+
+    fn property(&self, name: &str) -> Option<String> {
+        match name {
+            "project.groupId" | "pom.groupId" | "groupId" => self
+                .group_id
+                .clone()
+                .or_else(|| self.parent.as_ref().and_then(|parent| parent.group_id.clone())),
+            "project.artifactId" | "pom.artifactId" | "artifactId" => self.artifact_id.clone(),
+            "project.version" | "pom.version" | "version" => self
+                .version
+                .clone()
+                .or_else(|| self.parent.as_ref().map(|parent| parent.version.clone())),
+            "project.parent.version" => self.parent.as_ref().map(|parent| parent.version.clone()),
+            _ => self.properties.get(name).cloned(),
+        }
+    }
+
+    /// Replaces `${property}` in the value. None if a property is not defined in this POM
+    pub fn interpolate(&self, value: &str) -> Option<String> {
+        self.interpolate_depth(value, 0)
+    }
+
+    fn interpolate_depth(&self, value: &str, depth: usize) -> Option<String> {
+        let start = match value.find("${") {
+            Some(start) => start,
+            None => return Some(value.trim().to_string()),
+        };
+        // Properties can reference other properties. The depth stops cycles
+        if depth > 16 {
+            return None;
+        }
+        let end = start + value[start..].find('}')?;
+        let property = self.property(&value[start + 2..end])?;
+        let value = format!("{}{property}{}", &value[..start], &value[end + 1..]);
+        self.interpolate_depth(&value, depth + 1)
+    }
+
+    /// The dependencies that can show up in the public api. Test dependencies are skipped.
+    /// So are dependencies whose coordinates can not be resolved with this POM alone
+    pub fn artifacts(&self) -> Vec<Artifact> {
+        let managed: HashMap<(&str, &str), &str> = self
+            .dependency_management
+            .iter()
+            .flat_map(|management| management.dependencies.dependency.iter())
+            .filter_map(|dependency| {
+                let version = dependency.version.as_deref()?;
+                Some((
+                    (
+                        dependency.group_id.as_deref()?,
+                        dependency.artifact_id.as_str(),
+                    ),
+                    version,
+                ))
+            })
+            .collect();
+        self.dependencies
+            .dependency
+            .iter()
+            .filter(|dependency| dependency.scope.as_deref() != Some("test"))
+            .filter_map(|dependency| {
+                let raw_group_id = match dependency.group_id.as_deref() {
+                    Some(group_id) => group_id,
+                    None => {
+                        debug!("Skipping {} without a groupId", dependency.artifact_id);
+                        return None;
+                    }
+                };
+                let (group_id, artifact_id) = match (
+                    self.interpolate(raw_group_id),
+                    self.interpolate(&dependency.artifact_id),
+                ) {
+                    (Some(group_id), Some(artifact_id)) => (group_id, artifact_id),
+                    _ => {
+                        debug!(
+                            "Skipping {raw_group_id}:{}. It uses a property of another POM",
+                            dependency.artifact_id
+                        );
+                        return None;
+                    }
+                };
+                let version = dependency
+                    .version
+                    .as_deref()
+                    .or_else(|| {
+                        managed
+                            .get(&(raw_group_id, dependency.artifact_id.as_str()))
+                            .copied()
+                    })
+                    .and_then(|version| self.interpolate(version))
+                    // Ranges like [1.0,2.0) are left to the latest version
+                    .filter(|version| !version.starts_with(['[', '(']));
+                Some(Artifact {
+                    project: format!("{group_id}:{artifact_id}"),
+                    version,
+                    scope: dependency.scope.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Resolves the properties in the parts that are kept
+    pub fn metadata(&self) -> PomMetadata {
+        let interpolate = |value: &Option<String>| {
+            value.as_deref().map(|value| {
+                self.interpolate(value)
+                    .unwrap_or_else(|| value.trim().to_string())
+            })
+        };
+        PomMetadata {
+            name: interpolate(&self.name),
+            description: interpolate(&self.description),
+            url: interpolate(&self.url),
+            licenses: self.licenses.license.clone(),
+            scm: self.scm.as_ref().map(|scm| Scm {
+                url: interpolate(&scm.url),
+                connection: interpolate(&scm.connection),
+                developer_connection: interpolate(&scm.developer_connection),
+                tag: interpolate(&scm.tag),
+            }),
+            dependencies: self.artifacts(),
+        }
+    }
+}
+
+/// Downloads and parses the POM of a version. None if the repository has no POM
+pub async fn download_pom(
+    repository: &Repository,
+    client: &Client,
+    project: &str,
+    artifact_id: &str,
+    version: &str,
+    file_version: &str,
+) -> Result<Option<Pom>, Error> {
+    let url = format!(
+        "{}/{}/{version}/{artifact_id}-{file_version}.pom",
+        repository.address,
+        project_to_path(project)
+    );
+    let response = repository.get(client, &url).await?;
+    if !response.status().is_success() {
+        debug!("No POM at {url}: {}", response.status());
+        return Ok(None);
+    }
+    let text = response.text().await?;
+    Pom::parse(&text).map(Some)
+}
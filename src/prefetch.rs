@@ -0,0 +1,219 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use clap::Args;
+use futures_util::StreamExt;
+use reqwest::Client;
+
+use crate::project::{Project, Version};
+use crate::project_processor::{build_client, process_project, ProjectRequest};
+use crate::repository::{open_repositories, Repository};
+use crate::version::MavenVersion;
+use crate::{Config, Error};
+
+#[derive(Args)]
+pub struct PrefetchArgs {
+    /// The repository to build the javadoc from
+    pub repository: String,
+    /// Coordinates in the form of `groupId:artifactId[:selector]`
+    pub coordinates: Vec<String>,
+    /// A file with one coordinate per line. Empty lines and lines starting with # are ignored
+    #[clap(short, long)]
+    pub file: Option<PathBuf>,
+    /// Used for coordinates without a selector. `latest`, `all`, `last:N` or a version
+    #[clap(short, long, default_value = "latest")]
+    pub versions: VersionSelector,
+}
+
+/// Which versions of a project to build
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionSelector {
+    Latest,
+    All,
+    /// The newest N versions in the maven-metadata.xml by Maven version order
+    Last(usize),
+    Exact(String),
+}
+
+impl FromStr for VersionSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err("Version selector can not be empty".to_string()),
+            "latest" => Ok(VersionSelector::Latest),
+            "all" => Ok(VersionSelector::All),
+            _ => match s.strip_prefix("last:") {
+                Some(amount) => amount
+                    .parse()
+                    .map(VersionSelector::Last)
+                    .map_err(|_| format!("Invalid amount of versions {amount}")),
+                None => Ok(VersionSelector::Exact(s.to_string())),
+            },
+        }
+    }
+}
+
+/// `groupId:artifactId[:selector]`. The selector falls back to `default`
+fn parse_coordinate(
+    coordinate: &str,
+    default: &VersionSelector,
+) -> Result<(String, VersionSelector), String> {
+    let mut parts = coordinate.splitn(3, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(group), Some(artifact), selector) if !group.is_empty() && !artifact.is_empty() => {
+            let selector = match selector {
+                Some(selector) => selector.parse()?,
+                None => default.clone(),
+            };
+            Ok((format!("{group}:{artifact}"), selector))
+        }
+        _ => Err(format!("Invalid coordinate {coordinate}")),
+    }
+}
+
+/// Turns the selector into the versions to request. None is the latest version
+async fn resolve_versions(
+    repository: &Arc<Repository>,
+    client: &Client,
+    project: &str,
+    selector: VersionSelector,
+) -> Result<Vec<Option<String>>, Error> {
+    let (all, last) = match selector {
+        VersionSelector::Latest => return Ok(vec![None]),
+        VersionSelector::Exact(version) => return Ok(vec![Some(version)]),
+        VersionSelector::All => (true, 0),
+        VersionSelector::Last(last) => (false, last),
+    };
+    let project = Project {
+        name: project.to_string(),
+        ..Default::default()
+    };
+    let mut versions = project
+        .download_deploy_data(repository, client)
+        .await?
+        .versioning
+        .versions
+        .version;
+    // The maven-metadata.xml lists versions in upload order. Backports and redeploys are out of order
+    versions.sort_by_cached_key(|version| MavenVersion::parse(version));
+    let skip = if all {
+        0
+    } else {
+        versions.len().saturating_sub(last)
+    };
+    Ok(versions.into_iter().skip(skip).map(Some).collect())
+}
+
+/// Builds the request and checks that a javadoc was actually stored
+async fn prefetch_version(request: ProjectRequest, client: &Client) -> Result<String, Error> {
+    process_project(request.clone(), client).await?;
+    let project = request.repository.get_project(&request.project).await?;
+    let version = match request.version.clone() {
+        Some(version) => Some(version),
+        None => project.as_ref().and_then(|project| project.latest.clone()),
+    };
+    let stored = project
+        .as_ref()
+        .zip(version.as_ref())
+        .and_then(|(project, version)| project.versions.get(version));
+    match (version, stored) {
+        (Some(version), Some(Version::Build { .. } | Version::BuildSnapshot { .. })) => Ok(version),
+        (Some(version), _) => Err(Error::NoJavadoc(version)),
+        (None, _) => Err(Error::NoJavadoc("latest".to_string())),
+    }
+}
+
+/// Builds every requested version without starting the server. Returns false if anything failed
+pub async fn prefetch(mut config: Config, args: PrefetchArgs) -> Result<bool, Error> {
+    let repository = match open_repositories(&mut config)?
+        .into_iter()
+        .find(|repository| repository.name == args.repository)
+    {
+        Some(repository) => Arc::new(repository),
+        None => {
+            println!("Repository {} not found", args.repository);
+            return Ok(false);
+        }
+    };
+
+    let mut coordinates = args.coordinates.clone();
+    if let Some(file) = args.file.as_ref() {
+        let content = tokio::fs::read_to_string(file).await?;
+        coordinates.extend(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string),
+        );
+    }
+
+    let client = build_client();
+    let mut failed = 0;
+    let mut requests = Vec::new();
+    for coordinate in coordinates {
+        let (project, selector) = match parse_coordinate(&coordinate, &args.versions) {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                println!("FAILED {coordinate}: {error}");
+                failed += 1;
+                continue;
+            }
+        };
+        match resolve_versions(&repository, &client, &project, selector).await {
+            Ok(versions) => requests.extend(versions.into_iter().map(|version| ProjectRequest {
+                repository: repository.clone(),
+                project: project.clone(),
+                version,
+                force: false,
+            })),
+            Err(error) => {
+                println!("FAILED {coordinate}: {error}");
+                failed += 1;
+            }
+        }
+    }
+
+    let total = requests.len();
+    // Builds of one project write the same database entry. They run one after another
+    let mut projects: Vec<Vec<ProjectRequest>> = Vec::new();
+    for request in requests {
+        match projects.iter_mut().find(|group| group[0].project == request.project) {
+            Some(group) => group.push(request),
+            None => projects.push(vec![request]),
+        }
+    }
+    let mut results = futures_util::stream::iter(projects)
+        .map(|group| {
+            let client = &client;
+            async move {
+                let mut results = Vec::with_capacity(group.len());
+                for request in group {
+                    let result = prefetch_version(request.clone(), client).await;
+                    results.push((request, result));
+                }
+                results
+            }
+        })
+        .buffer_unordered(config.workers.max(1));
+    let mut succeeded = 0;
+    while let Some(group) = results.next().await {
+        for (request, result) in group {
+            let requested = request.version.as_deref().unwrap_or("latest");
+            match result {
+                Ok(version) => {
+                    println!("OK {} {version}", request.project);
+                    succeeded += 1;
+                }
+                Err(error) => {
+                    println!("FAILED {} {requested}: {error}", request.project);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    println!("Prefetched {succeeded} of {total} versions. {failed} failed");
+    Ok(failed == 0)
+}
@@ -1,9 +1,10 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
+use handlebars::Handlebars;
 use log::debug;
-use lol_html::{HtmlRewriter, Settings};
 use maven_rs::maven_metadata::DeployMetadata;
 use maven_rs::quick_xml;
 use reqwest::Client;
@@ -11,9 +12,11 @@ use serde::{Deserialize, Serialize};
 use tokio::fs::{read, read_to_string};
 use tokio::io::AsyncWriteExt;
 
-use crate::repository::{project_to_path, Repository};
-use crate::{Error, Resources};
-use crate::html::rewrite_html;
+use crate::repository::{project_to_path, Repository, Storage};
+use crate::html::{rewrite_html, Header};
+use crate::links::LinkResolver;
+use crate::pom::PomMetadata;
+use crate::Error;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Project {
@@ -21,6 +24,46 @@ pub struct Project {
     pub versions: HashMap<String, Version>,
     pub latest: Option<String>,
     pub last_updated: Option<DateTime<Utc>>,
+    /// Last time any version of the project was viewed
+    #[serde(default)]
+    pub last_accessed: Option<DateTime<Utc>>,
+    /// Files of replaced builds. The sweeper removes them once requests can no longer be reading them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replaced: Vec<ReplacedBuild>,
+}
+
+/// What the project lists show of a project. Kept in memory so listing does not read every project
+#[derive(Debug, Clone)]
+pub struct ProjectListing {
+    pub name: String,
+    pub latest: Option<String>,
+    pub last_updated: Option<DateTime<Utc>>,
+    pub last_accessed: Option<DateTime<Utc>>,
+    /// Amount of versions with a javadoc
+    pub built: usize,
+}
+
+impl From<&Project> for ProjectListing {
+    fn from(project: &Project) -> Self {
+        ProjectListing {
+            name: project.name.clone(),
+            latest: project.latest.clone(),
+            last_updated: project.last_updated,
+            last_accessed: project.last_accessed,
+            built: project
+                .versions
+                .values()
+                .filter(|version| version.path().is_some())
+                .count(),
+        }
+    }
+}
+
+/// A file or folder of a build that is no longer served
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplacedBuild {
+    pub path: PathBuf,
+    pub replaced: DateTime<Utc>,
 }
 
 impl Project {
@@ -33,6 +76,26 @@ impl Project {
             None
         }
     }
+    /// Finds a version by the name used in urls. `latest`, a version or `{snapshot}@{build}`
+    pub fn resolve_version(&self, name: &str) -> Option<(String, Cow<'_, Version>)> {
+        if name == "latest" {
+            let latest = self.latest.as_ref()?;
+            return self
+                .versions
+                .get(latest)
+                .map(|version| (latest.clone(), Cow::Borrowed(version)));
+        }
+        if let Some((base, build)) = name.split_once('@') {
+            return self
+                .versions
+                .get(base)?
+                .snapshot_build(build)
+                .map(|version| (name.to_string(), Cow::Owned(version)));
+        }
+        self.versions
+            .get(name)
+            .map(|version| (name.to_string(), Cow::Borrowed(version)))
+    }
     pub fn get_latest_version_mut(&mut self) -> Option<&mut Version> {
         if let Some(latest) = self.latest.as_ref() {
             // Should always be Some
@@ -41,15 +104,15 @@ impl Project {
             None
         }
     }
-    /// Rather or not should we update the project
+    /// Whether or not the maven-metadata.xml should be downloaded again to look for new versions
     pub fn should_update(&self, repository: impl AsRef<Repository>) -> bool {
-        if let Some(last_updated) = self.last_updated {
-            let now = Utc::now();
-            if (now - last_updated).num_hours() >= repository.as_ref().cache.time_til_update as i64 {
-                return true;
+        match self.last_updated {
+            Some(last_updated) => {
+                Utc::now() - last_updated
+                    >= Duration::minutes(repository.as_ref().cache.metadata_update as i64)
             }
+            None => true,
         }
-        true
     }
 
     pub async fn download_deploy_data(
@@ -63,7 +126,7 @@ impl Project {
             repository.as_ref().address,
             project_path
         );
-        let response = client.get(&url).send().await?;
+        let response = repository.as_ref().get(client, &url).await?;
         let response = response.error_for_status()?;
         let text = response.text().await?;
         let metadata: DeployMetadata = quick_xml::de::from_str(text.as_str())?;
@@ -98,21 +161,77 @@ impl Project {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Version {
     /// No Version Data
-    NoBuild { checked: DateTime<Utc> },
+    NoBuild {
+        checked: DateTime<Utc>,
+        /// Checks in a row that found nothing. Used to back off
+        #[serde(default)]
+        attempts: u32,
+    },
     /// Contains a release version
     Build {
+        /// The extracted folder or the jar depending on the storage
         path: PathBuf,
+        /// The verified sha1 of the jar
         sha1: Option<String>,
         built: DateTime<Utc>,
+        #[serde(default)]
+        storage: Storage,
+        #[serde(default)]
+        last_accessed: Option<DateTime<Utc>>,
+        /// What the POM says about the version. None if the repository has no POM
+        #[serde(default)]
+        pom: Option<PomMetadata>,
     },
     /// Contains a snapshot version
     BuildSnapshot {
         path: PathBuf,
+        /// The verified sha1 of the jar
+        #[serde(default)]
+        sha1: Option<String>,
         timestamp: DateTime<Utc>,
         built: DateTime<Utc>,
+        #[serde(default)]
+        storage: Storage,
+        #[serde(default)]
+        last_accessed: Option<DateTime<Utc>>,
+        /// `{yyyyMMdd.HHmmss}-{build number}` of this build
+        #[serde(default)]
+        build: Option<String>,
+        /// Older builds that are kept. Newest first
+        #[serde(default)]
+        history: Vec<SnapshotBuild>,
+        #[serde(default)]
+        pom: Option<PomMetadata>,
     },
 }
 
+/// An older build of a snapshot. Can be viewed with `{version}@{id}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotBuild {
+    /// `{yyyyMMdd.HHmmss}-{build number}`
+    pub id: String,
+    pub path: PathBuf,
+    pub sha1: Option<String>,
+    pub built: DateTime<Utc>,
+    pub storage: Storage,
+    #[serde(default)]
+    pub pom: Option<PomMetadata>,
+}
+
+impl SnapshotBuild {
+    /// A version that serves this build
+    pub fn as_version(&self) -> Version {
+        Version::Build {
+            path: self.path.clone(),
+            sha1: self.sha1.clone(),
+            built: self.built,
+            storage: self.storage,
+            last_accessed: None,
+            pom: self.pom.clone(),
+        }
+    }
+}
+
 pub struct FileResponse {
     pub file: Vec<u8>,
     pub content_type: String,
@@ -122,38 +241,133 @@ impl Version {
     /// Updates the timestamp of the version
     pub fn update_checked(&mut self, now: DateTime<Utc>) {
         match self {
-            Version::NoBuild { checked } => *checked = now,
+            Version::NoBuild { checked, .. } => *checked = now,
             Version::Build { built, .. } => *built = now,
             Version::BuildSnapshot { built, .. } => *built = now,
         }
     }
 
-    pub async fn load_file(&self, file: Option<String>) -> Result<Option<FileResponse>, Error> {
-        let result = match self {
+    /// Where the javadoc is stored. None if there is no build
+    pub fn path(&self) -> Option<&PathBuf> {
+        match self {
+            Version::NoBuild { .. } => None,
+            Version::Build { path, .. } | Version::BuildSnapshot { path, .. } => Some(path),
+        }
+    }
+
+    /// The metadata of the POM the version was built with
+    pub fn pom(&self) -> Option<&PomMetadata> {
+        match self {
+            Version::NoBuild { .. } => None,
+            Version::Build { pom, .. } | Version::BuildSnapshot { pom, .. } => pom.as_ref(),
+        }
+    }
+
+    /// Where the javadoc and the kept snapshot builds are stored
+    pub fn paths(&self) -> Vec<&PathBuf> {
+        match self {
+            Version::NoBuild { .. } => Vec::new(),
+            Version::Build { path, .. } => vec![path],
+            Version::BuildSnapshot { path, history, .. } => std::iter::once(path)
+                .chain(history.iter().map(|build| &build.path))
+                .collect(),
+        }
+    }
+
+    /// A build of a snapshot by its id. Either the current build or one kept in the history
+    pub fn snapshot_build(&self, id: &str) -> Option<Version> {
+        match self {
+            Version::BuildSnapshot { build, history, .. } => {
+                if build.as_deref() == Some(id) {
+                    Some(self.clone())
+                } else {
+                    history
+                        .iter()
+                        .find(|build| build.id == id)
+                        .map(SnapshotBuild::as_version)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    pub fn last_accessed(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Version::NoBuild { .. } => None,
+            Version::Build { last_accessed, .. } | Version::BuildSnapshot { last_accessed, .. } => {
+                *last_accessed
+            }
+        }
+    }
+
+    pub fn set_last_accessed(&mut self, now: DateTime<Utc>) {
+        match self {
+            Version::NoBuild { .. } => {}
+            Version::Build { last_accessed, .. } | Version::BuildSnapshot { last_accessed, .. } => {
+                *last_accessed = Some(now)
+            }
+        }
+    }
+
+    /// The last access or when it was built if it has never been viewed
+    pub fn last_used(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Version::NoBuild { .. } => None,
+            Version::Build {
+                built,
+                last_accessed,
+                ..
+            }
+            | Version::BuildSnapshot {
+                built,
+                last_accessed,
+                ..
+            } => Some(last_accessed.unwrap_or(*built)),
+        }
+    }
+
+    /// Loads a file out of the javadoc. HTML pages get the rendered header injected
+    pub async fn load_file(
+        &self,
+        file: Option<String>,
+        repository: &Repository,
+        header: &Header<'_>,
+        handlebars: &Handlebars<'_>,
+    ) -> Result<Option<FileResponse>, Error> {
+        let (result, storage) = match self {
             Version::NoBuild { .. } => {
                 return Ok(None);
             }
-            Version::Build { path, .. } => path,
-            Version::BuildSnapshot { path, .. } => path,
+            Version::Build { path, storage, .. } => (path, storage),
+            Version::BuildSnapshot { path, storage, .. } => (path, storage),
         };
         let x = file
             .as_ref()
             .and_then(|f| if f.is_empty() { None } else { Some(f.as_str()) })
             .unwrap_or("index.html");
-        let file = result.join(x);
-
-        debug!("Loading file: {:?}", file);
-        let text = if file.exists() {
-            let content = read(&file).await?;
-            if file.extension().map(|e| e == "html").unwrap_or(false) {
-                debug!("Rewriting HTML");
-                // TODO rewrite_html(&content).unwrap()
-                content
-            } else {
-                content
+
+        debug!("Loading file: {x} from {:?}", result);
+        let content = match storage {
+            Storage::Extract => {
+                let file = result.join(x);
+                if !file.exists() {
+                    return Ok(None);
+                }
+                read(&file).await?
             }
+            Storage::Jar => match repository.jars.read(result, x).await? {
+                Some(content) => content,
+                None => return Ok(None),
+            },
+        };
+        let text = if Path::new(x).extension().map(|e| e == "html").unwrap_or(false) {
+            debug!("Rewriting HTML");
+            let css = header.render_css(handlebars)?;
+            let header = header.render(handlebars)?;
+            let links = LinkResolver::for_version(repository, self);
+            rewrite_html(&content, &header, &css, &links)?
         } else {
-            return Ok(None);
+            content
         };
         Ok(Some(FileResponse {
             file: text,
@@ -163,22 +377,21 @@ impl Version {
 
 
     /// Should the system check for updates
+    ///
+    /// Releases are only checked if the repository allows redeploying. Versions without javadoc back off
     pub fn should_be_sent_for_rebuilding(&self, repo: impl AsRef<Repository>) -> bool {
         let now = Utc::now();
-
+        let repo = repo.as_ref();
         match self {
-            Version::NoBuild { checked } => {
-                let difference = *checked - now;
-                difference.num_hours() >= repo.as_ref().cache.time_til_update as i64
+            Version::NoBuild { checked, attempts } => {
+                now - *checked >= repo.cache.no_build_ttl(*attempts)
             }
             Version::Build { built, .. } => {
-                //TODO take the repository's update policy into account
-                let difference = *built - now;
-                difference.num_hours() >= repo.as_ref().cache.time_til_update as i64
+                repo.allows_redeploy
+                    && now - *built >= Duration::hours(repo.cache.time_til_update as i64)
             }
             Version::BuildSnapshot { built, .. } => {
-                let difference = *built - now;
-                difference.num_hours() >= repo.as_ref().cache.time_til_update as i64
+                now - *built >= Duration::minutes(repo.cache.snapshot_update as i64)
             }
         }
     }
@@ -0,0 +1,106 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+/// How long finished jobs are remembered
+pub const JOB_RETENTION_HOURS: i64 = 24;
+/// How long a failed job, or one that finished without a javadoc, blocks new requests for the same version
+pub const FAILED_RETRY_MINUTES: i64 = 5;
+
+/// The lifecycle of a queued request
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state")]
+pub enum Job {
+    Pending {
+        queued: DateTime<Utc>,
+    },
+    Running {
+        queued: DateTime<Utc>,
+        started: DateTime<Utc>,
+    },
+    Finished {
+        queued: DateTime<Utc>,
+        started: DateTime<Utc>,
+        finished: DateTime<Utc>,
+    },
+    Failed {
+        queued: DateTime<Utc>,
+        started: DateTime<Utc>,
+        finished: DateTime<Utc>,
+        error: String,
+    },
+}
+
+impl Job {
+    pub fn new() -> Self {
+        Job::Pending { queued: Utc::now() }
+    }
+    /// Pending or Running
+    pub fn in_flight(&self) -> bool {
+        matches!(self, Job::Pending { .. } | Job::Running { .. })
+    }
+    pub fn queued(&self) -> DateTime<Utc> {
+        match self {
+            Job::Pending { queued }
+            | Job::Running { queued, .. }
+            | Job::Finished { queued, .. }
+            | Job::Failed { queued, .. } => *queued,
+        }
+    }
+    fn started(&self) -> DateTime<Utc> {
+        match self {
+            Job::Pending { queued } => *queued,
+            Job::Running { started, .. }
+            | Job::Finished { started, .. }
+            | Job::Failed { started, .. } => *started,
+        }
+    }
+    pub fn start(&mut self) {
+        *self = Job::Running {
+            queued: self.queued(),
+            started: Utc::now(),
+        };
+    }
+    pub fn finish(&mut self, error: Option<String>) {
+        let (queued, started, finished) = (self.queued(), self.started(), Utc::now());
+        *self = match error {
+            None => Job::Finished {
+                queued,
+                started,
+                finished,
+            },
+            Some(error) => Job::Failed {
+                queued,
+                started,
+                finished,
+                error,
+            },
+        };
+    }
+    /// Failed less than [FAILED_RETRY_MINUTES] ago
+    pub fn recently_failed(&self, now: DateTime<Utc>) -> bool {
+        match self {
+            Job::Failed { finished, .. } => {
+                now - *finished < Duration::minutes(FAILED_RETRY_MINUTES)
+            }
+            _ => false,
+        }
+    }
+    /// Finished without an error less than [FAILED_RETRY_MINUTES] ago
+    pub fn recently_finished(&self, now: DateTime<Utc>) -> bool {
+        match self {
+            Job::Finished { finished, .. } => {
+                now - *finished < Duration::minutes(FAILED_RETRY_MINUTES)
+            }
+            _ => false,
+        }
+    }
+    /// Finished jobs older than [JOB_RETENTION_HOURS] are forgotten
+    pub fn expired(&self, now: DateTime<Utc>) -> bool {
+        match self {
+            Job::Finished { finished, .. } | Job::Failed { finished, .. } => {
+                now - *finished > Duration::hours(JOB_RETENTION_HOURS)
+            }
+            _ => false,
+        }
+    }
+}
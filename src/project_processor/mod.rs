@@ -1,22 +1,34 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 
 use bytes::Bytes;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use futures_util::StreamExt;
-use log::{error, info};
+use log::{error, info, warn};
 use maven_rs::maven_metadata::DeployMetadata;
 use maven_rs::quick_xml::de;
 use maven_rs::snapshot_metadata::SnapshotMetadata;
 use reqwest::{Client, ClientBuilder};
 use tokio::fs::{remove_file, OpenOptions};
 use tokio::io::AsyncWriteExt;
-use tokio::sync::mpsc::Receiver;
 
-use crate::project::{Project, Version};
-use crate::repository::Repository;
-use crate::Error;
+use crate::cache::version_files;
+use crate::pom::{download_pom, PomMetadata};
+use crate::project::{Project, ReplacedBuild, SnapshotBuild, Version};
+use crate::project_processor::queue::BuildQueue;
+use crate::project_processor::verify::{verify_checksums, verify_signature, Digests};
+use crate::repository::{Repository, Storage};
+use crate::{metrics, Error};
+
+/// Builds are written to a unique folder inside the project and only become visible once the database points at them
+pub static BUILDS_FOLDER: &str = ".builds";
+
+pub mod jobs;
+pub mod queue;
+pub mod verify;
 
 #[derive(Debug, Clone)]
 pub struct ProjectRequest {
@@ -24,16 +36,43 @@ pub struct ProjectRequest {
     /// The project id
     pub project: String,
     pub version: Option<String>,
+    /// Ignore the cache rules. The maven-metadata.xml is downloaded again and the version is rebuilt
+    pub force: bool,
 }
 
-pub async fn processor(cache: PathBuf, mut queue: Receiver<ProjectRequest>) {
-    let client = ClientBuilder::new()
+/// The client used to talk to the upstream repositories
+pub fn build_client() -> Client {
+    ClientBuilder::new()
         .user_agent("My Javadoc Generator")
         .build()
-        .unwrap();
-    while let Some(request) = queue.recv().await {
-        if let Err(error) = process_project(request, &client).await {
-            error!("Failed to process request {error}")
+        .unwrap()
+}
+
+/// Starts the workers that process the queue
+pub fn processor(queue: Arc<BuildQueue>, workers: usize) {
+    let client = build_client();
+    for _ in 0..workers.max(1) {
+        tokio::spawn(worker(queue.clone(), client.clone()));
+    }
+}
+
+async fn worker(queue: Arc<BuildQueue>, client: Client) {
+    loop {
+        let request = queue.next().await;
+        let start = Instant::now();
+        let result = process_project(request.clone(), &client).await;
+        let outcome = match &result {
+            Ok(_) => "success",
+            Err(error) => {
+                error!("Failed to process request {error}");
+                "failure"
+            }
+        };
+        metrics::JOB_DURATION
+            .with_label_values(&[&request.repository.name, outcome])
+            .observe(start.elapsed().as_secs_f64());
+        if let Err(error) = queue.finish(&request, &result).await {
+            error!("Failed to update the queue journal {error}")
         }
     }
 }
@@ -65,21 +104,42 @@ pub async fn process_project(
             versions: HashMap::new(),
             latest: None,
             last_updated: None,
+            last_accessed: None,
+            replaced: Vec::new(),
         });
     let now = Utc::now();
 
-    info!("Updating Deploy Data");
-    javadoc_project.last_updated = Some(now.clone());
-    // Download the latest maven-metadata.xml
-    let deploy_data = javadoc_project
-        .download_deploy_data(&project_request.repository, client)
-        .await?;
+    // The saved maven-metadata.xml is good enough if it is fresh and knows the requested version
+    let cached_deploy_data = match project_request.version.as_ref() {
+        Some(version)
+            if !project_request.force
+                && !javadoc_project.should_update(&project_request.repository) =>
+        {
+            javadoc_project
+                .get_deploy_data(&project_request.repository)
+                .await
+                .ok()
+                .filter(|data| data.versioning.versions.version.contains(version))
+        }
+        _ => None,
+    };
+    let deploy_data = match cached_deploy_data {
+        Some(deploy_data) => deploy_data,
+        None => {
+            info!("Updating Deploy Data");
+            javadoc_project.last_updated = Some(now.clone());
+            // Download the latest maven-metadata.xml
+            javadoc_project
+                .download_deploy_data(&project_request.repository, client)
+                .await?
+        }
+    };
 
     let (should_update, version_text) = if let Some(version_text) = project_request.version.as_ref()
     {
         if let Some(version) = javadoc_project.versions.get_mut(version_text) {
             match version {
-                Version::NoBuild { checked } => {
+                Version::NoBuild { checked, attempts } => {
                     if deploy_data
                         .versioning
                         .versions
@@ -88,12 +148,16 @@ pub async fn process_project(
                     {
                         (true, version_text)
                     } else {
-                        // The version is not available
+                        // The version is not available. Wait longer before checking again
+                        *checked = now;
+                        *attempts += 1;
                         (false, version_text)
                     }
                 }
                 version => {
-                    if version.should_be_sent_for_rebuilding(&project_request.repository) {
+                    if project_request.force
+                        || version.should_be_sent_for_rebuilding(&project_request.repository)
+                    {
                         version.update_checked(now.clone());
                         (true, version_text)
                     } else {
@@ -111,7 +175,9 @@ pub async fn process_project(
         // Check if the latest version is the same as the latest version
         if javadoc_project.latest.as_ref() == deploy_data.get_latest_version() {
             if let Some(version) = javadoc_project.get_latest_version_mut() {
-                if version.should_be_sent_for_rebuilding(&project_request.repository) {
+                if project_request.force
+                    || version.should_be_sent_for_rebuilding(&project_request.repository)
+                {
                     version.update_checked(now.clone());
                     (true, javadoc_project.latest.as_ref().unwrap())
                 } else {
@@ -128,13 +194,17 @@ pub async fn process_project(
         }
     };
 
+    // Builds that were replaced. Removed once the new build is saved
+    let mut replaced = Vec::new();
+    // The new build. Indexed once it is saved so a failed save does not leave its files behind
+    let mut fresh_build = None;
     if should_update {
         if version_text.ends_with("-SNAPSHOT") {
             let url = format!(
                 "{}/{project_path}/{version_text}/maven-metadata.xml",
                 project_request.repository.address
             );
-            let response = client.get(&url).send().await?;
+            let response = project_request.repository.get(client, &url).await?;
             if response.status().is_success() {
                 let string = response.text().await?;
                 let maven_file = project_location.join(version_text);
@@ -149,8 +219,14 @@ pub async fn process_project(
                     .await?
                     .write_all(string.as_bytes())
                     .await?;
-                let metadata: SnapshotMetadata = de::from_str(&string)?;
-                if let Some(value) = metadata.versioning.snapshot_versions {
+                let metadata = de::from_str::<SnapshotMetadata>(&string)
+                    .map_err(|error| warn!("Unreadable snapshot metadata {url}: {error}"))
+                    .ok()
+                    .and_then(|metadata| {
+                        let versioning = metadata.versioning;
+                        Some((versioning.snapshot_versions?, versioning.snapshot))
+                    });
+                if let Some((value, snapshot)) = metadata {
                     let option = value.snapshot_version.into_iter().find(|x| {
                         if let Some(x) = x.classifier.as_ref() {
                             x.eq("javadoc")
@@ -159,17 +235,33 @@ pub async fn process_project(
                         }
                     });
                     if let Some(value) = option {
+                        let build_id = snapshot_build_id(version_text, &value.value);
                         if let Some(javadoc_version) = javadoc_project.versions.get(version_text){
-                            if let Version::BuildSnapshot { timestamp, .. } = javadoc_version {
-                                if let Some(snapshot) = metadata.versioning.snapshot {
-                                    if snapshot.timestamp.as_ref().eq(&Some(timestamp)) {
-                                        // The timestamp is the same, so we don't need to rebuild
-                                        return Ok(());
-                                    }
+                            if let Version::BuildSnapshot {
+                                timestamp, build, ..
+                            } = javadoc_version
+                            {
+                                let same_build = build_id.is_some() && build.eq(&build_id);
+                                let same_timestamp = snapshot
+                                    .map(|snapshot| {
+                                        snapshot.timestamp.as_ref().eq(&Some(timestamp))
+                                    })
+                                    .unwrap_or(false);
+                                if same_build || same_timestamp {
+                                    // The timestamp is the same, so we don't need to rebuild. Only save when it was checked
+                                    let version_text = version_text.clone();
+                                    save_build(
+                                        &project_request.repository,
+                                        javadoc_project,
+                                        &version_text,
+                                        Vec::new(),
+                                    )
+                                    .await?;
+                                    return Ok(());
                                 }
                             }
                         }
-                        if build_javadoc(
+                        if let Some(built) = build_javadoc(
                             &project_request,
                             client,
                             &project_location,
@@ -179,12 +271,65 @@ pub async fn process_project(
                         )
                         .await?
                         {
+                            fresh_build = Some(built.path.clone());
+                            let pom = fetch_pom(
+                                &project_request,
+                                client,
+                                &deploy_data,
+                                version_text,
+                                &value.value,
+                            )
+                            .await;
+                            let previous = javadoc_project.versions.remove(version_text);
+                            let last_accessed = previous.as_ref().and_then(Version::last_accessed);
+                            let mut history = Vec::new();
+                            match previous {
+                                Some(Version::BuildSnapshot {
+                                    path,
+                                    sha1,
+                                    built,
+                                    storage,
+                                    build: Some(id),
+                                    history: previous_history,
+                                    pom,
+                                    ..
+                                }) => {
+                                    history.push(SnapshotBuild {
+                                        id,
+                                        path,
+                                        sha1,
+                                        built,
+                                        storage,
+                                        pom,
+                                    });
+                                    history.extend(previous_history);
+                                }
+                                // Builds without an id can not be requested so they are not kept
+                                Some(previous) => replaced.push(previous),
+                                None => {}
+                            }
+                            let keep = project_request
+                                .repository
+                                .cache
+                                .snapshot_history
+                                .saturating_sub(1);
+                            if history.len() > keep {
+                                replaced.extend(
+                                    history.split_off(keep).iter().map(SnapshotBuild::as_version),
+                                );
+                            }
                             javadoc_project.versions.insert(
                                 version_text.to_string(),
                                 Version::BuildSnapshot {
-                                    path: project_location.join(version_text),
+                                    path: built.path,
+                                    sha1: built.sha1,
                                     timestamp: value.updated.unwrap_or(now.clone()),
                                     built: now,
+                                    storage: project_request.repository.storage,
+                                    last_accessed,
+                                    build: build_id,
+                                    history,
+                                    pom,
                                 },
                             );
                         } else {
@@ -193,7 +338,16 @@ pub async fn process_project(
                     }
                 }
             }
-        } else if build_javadoc(
+            // Nothing was built and there is no older build to serve
+            if javadoc_project
+                .versions
+                .get(version_text)
+                .and_then(Version::path)
+                .is_none()
+            {
+                mark_no_build(&mut javadoc_project.versions, version_text, now);
+            }
+        } else if let Some(built) = build_javadoc(
             &project_request,
             client,
             &project_location,
@@ -203,28 +357,206 @@ pub async fn process_project(
         )
         .await?
         {
-            javadoc_project.versions.insert(
+            fresh_build = Some(built.path.clone());
+            let pom = fetch_pom(
+                &project_request,
+                client,
+                &deploy_data,
+                version_text,
+                version_text,
+            )
+            .await;
+            let last_accessed = javadoc_project
+                .versions
+                .get(version_text)
+                .and_then(Version::last_accessed);
+            replaced.extend(javadoc_project.versions.insert(
                 version_text.to_string(),
                 Version::Build {
-                    path: project_location.join(version_text),
-                    sha1: None,
+                    path: built.path,
+                    sha1: built.sha1,
                     built: now,
+                    storage: project_request.repository.storage,
+                    last_accessed,
+                    pom,
                 },
-            );
+            ));
         } else {
-            javadoc_project
-                .versions
-                .insert(version_text.to_string(), Version::NoBuild { checked: now });
+            mark_no_build(&mut javadoc_project.versions, version_text, now);
         }
     }
 
-    project_request
-        .repository
-        .save_project(javadoc_project)
+    let version_text = version_text.clone();
+    let current = javadoc_project
+        .versions
+        .get(&version_text)
+        .and_then(Version::path)
+        .cloned()
+        .unwrap_or_default();
+    let replaced = replaced
+        .iter()
+        .flat_map(|previous| replaced_files(&project_location, &version_text, previous, &current))
+        .map(|path| ReplacedBuild {
+            path,
+            replaced: now,
+        })
+        .collect();
+    save_build(&project_request.repository, javadoc_project, &version_text, replaced).await?;
+    if let Some(javadoc) = fresh_build {
+        index_version(&project_request, &version_text, &javadoc, now).await;
+        save_report(&project_location, &version_text, &javadoc, now).await;
+    }
+    Ok(())
+}
+
+/// Saves what the build changed. The version, the latest version, when the maven-metadata.xml was updated and the replaced builds.
+///
+/// The project was read before the build. The sweeper can have changed the rest of it since
+async fn save_build(
+    repository: &Repository,
+    mut project: Project,
+    version: &str,
+    replaced: Vec<ReplacedBuild>,
+) -> Result<(), Error> {
+    let built = project.versions.get(version);
+    let updated = repository
+        .update_project(&project.name, |current| {
+            if project.latest.is_some() {
+                current.latest = project.latest.clone();
+            }
+            current.last_updated = current.last_updated.max(project.last_updated);
+            if let Some(built) = built {
+                let mut built = built.clone();
+                // Views of the version are flushed while it builds
+                let accessed = current.versions.get(version).and_then(Version::last_accessed);
+                if let Some(accessed) = accessed.filter(|_| accessed > built.last_accessed()) {
+                    built.set_last_accessed(accessed);
+                }
+                current.versions.insert(version.to_string(), built);
+            }
+            current.replaced.extend(replaced.iter().cloned());
+        })
         .await?;
+    if updated.is_none() {
+        project.replaced = replaced;
+        repository.save_project(project).await?;
+    }
     Ok(())
 }
 
+/// Records that the version has no javadoc. Checks in a row back off
+fn mark_no_build(versions: &mut HashMap<String, Version>, version: &str, now: DateTime<Utc>) {
+    let attempts = match versions.get(version) {
+        Some(Version::NoBuild { attempts, .. }) => attempts + 1,
+        _ => 0,
+    };
+    versions.insert(
+        version.to_string(),
+        Version::NoBuild {
+            checked: now,
+            attempts,
+        },
+    );
+}
+
+/// Adds the built version to the search index. A javadoc without search files is not an error
+async fn index_version(
+    project_request: &ProjectRequest,
+    version: &str,
+    javadoc: &Path,
+    built: DateTime<Utc>,
+) {
+    if let Err(error) = crate::search::index_version(
+        &project_request.repository,
+        &project_request.project,
+        version,
+        javadoc,
+        built,
+    )
+    .await
+    {
+        error!(
+            "Failed to index {project} {version}: {error}",
+            project = project_request.project
+        );
+    }
+}
+
+/// Generates the deprecation and `@since` report of the built version. Without it the report is generated when it is viewed
+async fn save_report(project_location: &Path, version: &str, javadoc: &Path, built: DateTime<Utc>) {
+    if let Err(error) =
+        crate::report::save_report(project_location, version, javadoc, built).await
+    {
+        error!("Failed to generate the report of {version}: {error}");
+    }
+}
+
+/// The metadata of the built version's POM. A missing or broken POM is not an error
+async fn fetch_pom(
+    project_request: &ProjectRequest,
+    client: &Client,
+    deploy_data: &DeployMetadata,
+    version: &str,
+    file_version: &str,
+) -> Option<PomMetadata> {
+    match download_pom(
+        &project_request.repository,
+        client,
+        &project_request.project,
+        &deploy_data.artifact_id,
+        version,
+        file_version,
+    )
+    .await
+    {
+        Ok(pom) => pom.map(|pom| pom.metadata()),
+        Err(error) => {
+            warn!(
+                "Failed to read the POM of {project} {version}: {error}",
+                project = project_request.project
+            );
+            None
+        }
+    }
+}
+
+/// `{yyyyMMdd.HHmmss}-{build number}` out of a timestamped snapshot version like `1.2-20261001.120000-5`
+fn snapshot_build_id(version: &str, timestamped: &str) -> Option<String> {
+    let base = version.strip_suffix("-SNAPSHOT")?;
+    timestamped
+        .strip_prefix(base)?
+        .strip_prefix('-')
+        .filter(|id| !id.is_empty() && *id != "SNAPSHOT")
+        .map(str::to_string)
+}
+
+/// The files of a build that was replaced. The sweeper removes them after a grace period so requests reading them can finish
+fn replaced_files(
+    project_location: &Path,
+    version: &str,
+    previous: &Version,
+    current: &Path,
+) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = previous.path().cloned().into_iter().collect();
+    // Builds from before staging were extracted next to the downloaded jar
+    if previous.path() == Some(&project_location.join(version)) {
+        files.extend(
+            version_files(project_location, version, previous)
+                .into_iter()
+                .filter(|file| file.extension().map(|e| e == "jar").unwrap_or(false)),
+        );
+    }
+    files.retain(|file| file != current && file.exists());
+    files
+}
+
+/// A downloaded javadoc that passed verification
+struct BuiltJavadoc {
+    /// The extracted folder or the jar
+    path: PathBuf,
+    sha1: Option<String>,
+}
+
 async fn build_javadoc(
     project_request: &ProjectRequest,
     client: &Client,
@@ -232,7 +564,7 @@ async fn build_javadoc(
     deploy_data: &DeployMetadata,
     version: impl AsRef<str>,
     version_text: &String,
-) -> Result<bool, Error> {
+) -> Result<Option<BuiltJavadoc>, Error> {
     let project_path = project_to_path(&project_request.project);
     let url = format!(
         "{}/{project_path}/{version}/{}-{version_text}-javadoc.jar",
@@ -240,38 +572,79 @@ async fn build_javadoc(
         &deploy_data.artifact_id,
         version = version.as_ref()
     );
-    let response = client.get(&url).send().await?;
+    let response = project_request.repository.get(client, &url).await?;
     if response.status().is_success() {
-        let download_jar = project_location.join(format!("{}.jar", version_text));
-        if download_jar.exists() {
-            remove_file(&download_jar).await?;
-        }
+        let builds = project_location.join(BUILDS_FOLDER);
+        tokio::fs::create_dir_all(&builds).await?;
+        let build_id = format!("{}-{}", version.as_ref(), Utc::now().timestamp_millis());
+        let download_jar = builds.join(format!("{build_id}.jar"));
         let mut file = OpenOptions::new()
             .write(true)
-            .create(true)
+            .create_new(true)
             .open(&download_jar)
             .await?;
         let mut stream = response.bytes_stream();
-
-        while let Some(item) = stream.next().await {
-            let chunk: Bytes = item?;
-            file.write_all(chunk.as_ref()).await?;
+        let mut digests = Digests::default();
+        let repository = &project_request.repository;
+        let verified = async {
+            while let Some(item) = stream.next().await {
+                let chunk: Bytes = item?;
+                digests.update(chunk.as_ref());
+                file.write_all(chunk.as_ref()).await?;
+                metrics::DOWNLOADED_BYTES
+                    .with_label_values(&[&repository.name])
+                    .inc_by(chunk.len() as u64);
+            }
+            file.flush().await?;
+            let sha1 = verify_checksums(repository, client, &url, digests).await?;
+            verify_signature(repository, client, &url, &download_jar).await?;
+            Ok::<_, Error>(sha1)
         }
-        let output_folder = project_location.join(version.as_ref());
-        if output_folder.exists() && !output_folder.is_dir() {
-            remove_file(&output_folder).await?;
+        .await;
+        let sha1 = match verified {
+            Ok(sha1) => sha1,
+            Err(error) => {
+                // Never publish a jar that is incomplete or failed verification
+                remove_file(&download_jar).await?;
+                return Err(error);
+            }
+        };
+        if project_request.repository.storage == Storage::Jar {
+            return Ok(Some(BuiltJavadoc {
+                path: download_jar,
+                sha1,
+            }));
         }
-        if !output_folder.exists() {
-            tokio::fs::create_dir(&output_folder).await?;
+        let output_folder = builds.join(&build_id);
+        tokio::fs::create_dir(&output_folder).await?;
+        // Inflating a large jar takes a while. It runs on the blocking thread pool
+        let (folder, jar) = (output_folder.clone(), download_jar.clone());
+        let extracted = tokio::task::spawn_blocking(move || crate::zip::extract(&folder, &jar))
+            .await
+            .unwrap_or_else(|error| Err(std::io::Error::new(ErrorKind::Other, error).into()));
+        // The jar is not needed once it is extracted
+        remove_file(&download_jar).await?;
+        match extracted {
+            Ok(extracted) => {
+                metrics::EXTRACTED_BYTES
+                    .with_label_values(&[&project_request.repository.name])
+                    .inc_by(extracted);
+                Ok(Some(BuiltJavadoc {
+                    path: output_folder,
+                    sha1,
+                }))
+            }
+            Err(error) => {
+                tokio::fs::remove_dir_all(&output_folder).await?;
+                Err(error)
+            }
         }
-        crate::zip::extract(&output_folder, &download_jar)?;
-        Ok(true)
     } else {
         error!(
             "Failed to download javadoc for {project} {version}",
             project = project_request.project,
             version = version_text
         );
-        Ok(false)
+        Ok(None)
     }
 }
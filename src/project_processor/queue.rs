@@ -0,0 +1,271 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::Utc;
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, Notify};
+
+use crate::project_processor::jobs::Job;
+use crate::project_processor::ProjectRequest;
+use crate::repository::Repository;
+use crate::{metrics, Error};
+
+/// What is written to the journal. Repositories are saved by name
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct QueueEntry {
+    pub repository: String,
+    pub project: String,
+    pub version: Option<String>,
+}
+
+impl From<&ProjectRequest> for QueueEntry {
+    fn from(request: &ProjectRequest) -> Self {
+        QueueEntry {
+            repository: request.repository.name.clone(),
+            project: request.project.clone(),
+            version: request.version.clone(),
+        }
+    }
+}
+
+/// A line of the journal. Old journals have no force
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalEntry {
+    #[serde(flatten)]
+    entry: QueueEntry,
+    #[serde(default)]
+    force: bool,
+}
+
+impl QueueEntry {
+    /// Two entries for the same project can not be processed at the same time
+    fn same_project(&self, other: &QueueEntry) -> bool {
+        self.repository == other.repository && self.project == other.project
+    }
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    pending: VecDeque<ProjectRequest>,
+    jobs: HashMap<QueueEntry, Job>,
+    /// Pending and running requests that ignore the cache rules
+    forced: HashSet<QueueEntry>,
+    /// Running requests that got a forced request. They are queued again once they finish
+    rerun: HashSet<QueueEntry>,
+}
+
+impl QueueState {
+    /// Is an identical request pending or running
+    fn contains(&self, entry: &QueueEntry) -> bool {
+        self.jobs.get(entry).map(Job::in_flight).unwrap_or(false)
+    }
+
+    fn running(&self) -> impl Iterator<Item = &QueueEntry> {
+        self.jobs
+            .iter()
+            .filter(|(_, job)| matches!(job, Job::Running { .. }))
+            .map(|(entry, _)| entry)
+    }
+
+    fn push(&mut self, request: ProjectRequest) {
+        let entry = QueueEntry::from(&request);
+        if request.force {
+            self.forced.insert(entry.clone());
+        }
+        self.jobs.insert(entry, Job::new());
+        self.pending.push_back(request);
+    }
+
+    /// Makes an identical pending request ignore the cache rules.
+    /// A running request could have read the maven-metadata.xml already. It is queued again once it finishes.
+    /// False if nothing changed
+    fn force(&mut self, entry: &QueueEntry) -> bool {
+        let pending = self
+            .pending
+            .iter_mut()
+            .find(|request| QueueEntry::from(&**request).eq(entry));
+        match pending {
+            Some(request) if request.force => false,
+            Some(request) => {
+                request.force = true;
+                self.forced.insert(entry.clone());
+                true
+            }
+            None => self.rerun.insert(entry.clone()),
+        }
+    }
+}
+
+/// The queue of projects waiting to be processed.
+///
+/// Identical requests are coalesced and every change is written to a journal so the queue survives restarts.
+/// Requests for the same project are never handed out at the same time.
+#[derive(Debug)]
+pub struct BuildQueue {
+    journal: PathBuf,
+    state: Mutex<QueueState>,
+    notify: Notify,
+}
+
+impl BuildQueue {
+    /// Loads the queue from the journal. Entries for repositories that no longer exist are dropped
+    pub async fn load(
+        journal: PathBuf,
+        repositories: &[Arc<Repository>],
+    ) -> Result<BuildQueue, Error> {
+        let mut state = QueueState::default();
+        if journal.exists() {
+            let entries: Vec<JournalEntry> =
+                match serde_json::from_str(&tokio::fs::read_to_string(&journal).await?) {
+                    Ok(entries) => entries,
+                    Err(error) => {
+                        // Kept for inspection. A broken journal must not stop the server from starting
+                        let aside = journal.with_extension("json.corrupt");
+                        error!(
+                            "The queue journal is corrupt: {error}. Moved it to {}. Starting empty",
+                            aside.display()
+                        );
+                        tokio::fs::rename(&journal, &aside).await?;
+                        Vec::new()
+                    }
+                };
+            for JournalEntry { entry, force } in entries {
+                let repository = repositories
+                    .iter()
+                    .find(|repository| repository.name == entry.repository);
+                if let Some(repository) = repository {
+                    if !state.contains(&entry) {
+                        state.push(ProjectRequest {
+                            repository: repository.clone(),
+                            project: entry.project,
+                            version: entry.version,
+                            force,
+                        });
+                    }
+                } else {
+                    warn!("Dropping queued {entry:?}. The repository no longer exists");
+                }
+            }
+        }
+        metrics::QUEUE_DEPTH.set(state.pending.len() as i64);
+        Ok(BuildQueue {
+            journal,
+            state: Mutex::new(state),
+            notify: Notify::new(),
+        })
+    }
+
+    /// Adds the request to the queue.
+    ///
+    /// Returns false if an identical request is already pending or being processed.
+    /// A forced request makes an identical pending request ignore the cache rules as well.
+    /// An identical running request is processed again once it finishes
+    pub async fn send(&self, request: ProjectRequest) -> Result<bool, Error> {
+        let entry = QueueEntry::from(&request);
+        let mut state = self.state.lock().await;
+        if state.contains(&entry) {
+            if request.force && state.force(&entry) {
+                debug!("{entry:?} is already queued. It will ignore the cache rules");
+                self.save(&state).await?;
+            } else {
+                debug!("{entry:?} is already queued");
+            }
+            return Ok(false);
+        }
+        state.push(request);
+        metrics::QUEUE_DEPTH.set(state.pending.len() as i64);
+        self.save(&state).await?;
+        drop(state);
+        self.notify.notify_waiters();
+        Ok(true)
+    }
+
+    /// Waits for the next request whose project is not currently being processed
+    pub async fn next(&self) -> ProjectRequest {
+        loop {
+            let notified = self.notify.notified();
+            {
+                let mut state = self.state.lock().await;
+                let index = state.pending.iter().position(|request| {
+                    let entry = QueueEntry::from(request);
+                    !state.running().any(|running| running.same_project(&entry))
+                });
+                if let Some(request) = index.and_then(|index| state.pending.remove(index)) {
+                    metrics::QUEUE_DEPTH.set(state.pending.len() as i64);
+                    if let Some(job) = state.jobs.get_mut(&QueueEntry::from(&request)) {
+                        job.start();
+                    }
+                    return request;
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Marks the request as done and removes it from the journal. A request that was forced while running is queued again
+    pub async fn finish(
+        &self,
+        request: &ProjectRequest,
+        result: &Result<(), Error>,
+    ) -> Result<(), Error> {
+        let entry = QueueEntry::from(request);
+        let now = Utc::now();
+        let mut state = self.state.lock().await;
+        state.jobs.retain(|_, job| !job.expired(now));
+        state.forced.remove(&entry);
+        if let Some(job) = state.jobs.get_mut(&entry) {
+            job.finish(result.as_ref().err().map(ToString::to_string));
+        }
+        if state.rerun.remove(&entry) {
+            debug!("{entry:?} was forced while it was running. Queued again");
+            state.push(ProjectRequest {
+                force: true,
+                ..request.clone()
+            });
+            metrics::QUEUE_DEPTH.set(state.pending.len() as i64);
+        }
+        let result = self.save(&state).await;
+        drop(state);
+        self.notify.notify_waiters();
+        result
+    }
+
+    /// Is a request for the project pending or running. Its files must not be removed meanwhile
+    pub async fn busy(&self, repository: &str, project: &str) -> bool {
+        self.state.lock().await.jobs.iter().any(|(entry, job)| {
+            job.in_flight() && entry.repository == repository && entry.project == project
+        })
+    }
+
+    /// The job of an identical request
+    pub async fn job(&self, entry: &QueueEntry) -> Option<Job> {
+        self.state.lock().await.jobs.get(entry).cloned()
+    }
+
+    /// Running requests are saved as well. They did not finish if the server stops.
+    ///
+    /// Written to a temporary file that replaces the journal
+    async fn save(&self, state: &QueueState) -> Result<(), Error> {
+        let entries: Vec<JournalEntry> = state
+            .running()
+            .cloned()
+            .chain(state.pending.iter().map(QueueEntry::from))
+            .map(|entry| JournalEntry {
+                force: state.forced.contains(&entry) || state.rerun.contains(&entry),
+                entry,
+            })
+            .collect();
+        if let Some(parent) = self.journal.parent() {
+            if !parent.exists() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+        }
+        // A crash while writing leaves the old journal. Saves are serialized by the state lock
+        let temporary = self.journal.with_extension("json.tmp");
+        tokio::fs::write(&temporary, serde_json::to_string_pretty(&entries)?).await?;
+        tokio::fs::rename(&temporary, &self.journal).await?;
+        Ok(())
+    }
+}
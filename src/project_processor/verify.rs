@@ -0,0 +1,134 @@
+use std::path::Path;
+
+use log::{debug, info, warn};
+use reqwest::{Client, StatusCode};
+use sha1::{Digest, Sha1};
+use sha2::{Sha256, Sha512};
+use tokio::process::Command;
+
+use crate::repository::Repository;
+use crate::Error;
+
+/// Hashes the jar while it is being downloaded
+#[derive(Default)]
+pub struct Digests {
+    sha1: Sha1,
+    sha256: Sha256,
+    sha512: Sha512,
+}
+
+impl Digests {
+    pub fn update(&mut self, data: &[u8]) {
+        self.sha1.update(data);
+        self.sha256.update(data);
+        self.sha512.update(data);
+    }
+
+    /// Hex encoded digests in the order sha1, sha256, sha512
+    pub fn finish(self) -> [(&'static str, String); 3] {
+        [
+            ("sha1", hex::encode(self.sha1.finalize())),
+            ("sha256", hex::encode(self.sha256.finalize())),
+            ("sha512", hex::encode(self.sha512.finalize())),
+        ]
+    }
+}
+
+/// Downloads a sidecar file. None if the repository does not have it
+async fn download_sidecar(
+    repository: &Repository,
+    client: &Client,
+    url: &str,
+) -> Result<Option<String>, Error> {
+    let response = repository.get(client, url).await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let response = response.error_for_status()?;
+    Ok(Some(response.text().await?))
+}
+
+/// Compares the digests against the `.sha1`, `.sha256` and `.sha512` files next to the jar
+///
+/// Returns the sha1 if at least one checksum was verified
+pub async fn verify_checksums(
+    repository: &Repository,
+    client: &Client,
+    jar_url: &str,
+    digests: Digests,
+) -> Result<Option<String>, Error> {
+    let digests = digests.finish();
+    let mut verified = false;
+    for (algorithm, actual) in digests.iter() {
+        let url = format!("{jar_url}.{algorithm}");
+        let expected = match download_sidecar(repository, client, &url).await? {
+            Some(expected) => expected,
+            None => {
+                debug!("{url} does not exist");
+                continue;
+            }
+        };
+        // Some tools write `{hash}  {file name}`
+        let expected = expected.split_whitespace().next().unwrap_or_default();
+        if !expected.eq_ignore_ascii_case(actual) {
+            return Err(Error::ChecksumMismatch {
+                url,
+                expected: expected.to_string(),
+                actual: actual.clone(),
+            });
+        }
+        verified = true;
+    }
+    if verified {
+        Ok(Some(digests[0].1.clone()))
+    } else if repository.verification.require_checksum {
+        Err(Error::Unverified(format!("No checksum found for {jar_url}")))
+    } else {
+        warn!("No checksum found for {jar_url}. Continuing without verifying");
+        Ok(None)
+    }
+}
+
+/// Checks the `.asc` signature with gpgv if the repository has a keyring configured
+pub async fn verify_signature(
+    repository: &Repository,
+    client: &Client,
+    jar_url: &str,
+    jar: &Path,
+) -> Result<(), Error> {
+    let keyring = match repository.verification.keyring.as_ref() {
+        Some(keyring) => keyring,
+        None => return Ok(()),
+    };
+    let url = format!("{jar_url}.asc");
+    let signature = match download_sidecar(repository, client, &url).await? {
+        Some(signature) => signature,
+        None if repository.verification.require_signature => {
+            return Err(Error::Unverified(format!("No signature found for {jar_url}")));
+        }
+        None => {
+            warn!("No signature found for {jar_url}. Continuing without verifying");
+            return Ok(());
+        }
+    };
+    let signature_file = jar.with_extension("jar.asc");
+    tokio::fs::write(&signature_file, signature).await?;
+    let output = Command::new(&repository.verification.gpgv)
+        .arg("--keyring")
+        .arg(keyring)
+        .arg(&signature_file)
+        .arg(jar)
+        .output()
+        .await;
+    tokio::fs::remove_file(&signature_file).await?;
+    let output = output?;
+    if output.status.success() {
+        info!("Verified the signature of {jar_url}");
+        Ok(())
+    } else {
+        Err(Error::Unverified(format!(
+            "Invalid signature for {jar_url}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
@@ -0,0 +1,380 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use actix_web::HttpResponse;
+use chrono::{DateTime, Utc};
+use handlebars::Handlebars;
+use log::{debug, warn};
+use lol_html::errors::RewritingError;
+use lol_html::{element, text, HtmlRewriter, Settings};
+use serde::{Deserialize, Serialize};
+
+use crate::diff::ApiModel;
+use crate::project::{Project, Version};
+use crate::repository::{project_to_path, Repository};
+use crate::search::{JavadocFiles, SearchKind};
+use crate::site::render_page;
+use crate::{Error, SiteSettings};
+
+/// Where the description of a type starts. `div.description` is used by javadoc 8
+static CLASS_DESCRIPTION: &str = "section.class-description, div.description";
+/// Tags in the summary tables belong to the members that are described later
+static SUMMARY: &str = "section.summary, div.summary";
+/// The details of a member. Older javadoc places an anchor before each member
+static MEMBER_DETAIL: &str =
+    "section.detail[id], section.details a[id], div.details a[id], div.details a[name]";
+static DEPRECATION: &str =
+    ".deprecation-block, .deprecationBlock, .deprecated-label, .deprecatedLabel";
+static SIGNATURE: &str = ".annotations, .member-signature, .memberSignature, .type-signature, pre";
+
+/// Where the report of a version is saved. Next to the javadoc jar
+pub fn report_file(project_location: &Path, version: &str) -> PathBuf {
+    project_location.join(format!("{version}.report.json"))
+}
+
+/// The deprecation and `@since` tags of one element
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Tags {
+    pub deprecated: bool,
+    pub for_removal: bool,
+    pub deprecated_since: Option<String>,
+    pub since: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportEntry {
+    pub kind: SearchKind,
+    pub name: String,
+    /// Path inside the javadoc
+    pub path: String,
+    #[serde(flatten)]
+    pub tags: Tags,
+}
+
+/// The saved report of a version
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionReport {
+    /// When the javadoc that was parsed was built. A rebuilt snapshot needs a new report
+    pub built: DateTime<Utc>,
+    pub entries: Vec<ReportEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportItem {
+    pub kind: SearchKind,
+    pub name: String,
+    pub url: String,
+    #[serde(flatten)]
+    pub tags: Tags,
+}
+
+/// What the page and the api respond with
+#[derive(Debug, Serialize)]
+pub struct ProjectReport {
+    pub repository: String,
+    pub project: String,
+    pub version: String,
+    pub deprecated: Vec<ReportItem>,
+    /// Grouped by the `@since` value
+    pub since: BTreeMap<String, Vec<ReportItem>>,
+}
+
+#[derive(Default)]
+struct PageState {
+    /// The anchor of the member being read. Empty for the type itself
+    current: Option<String>,
+    expect_since: bool,
+    dt: String,
+    dd: String,
+    signature: String,
+    tags: HashMap<String, Tags>,
+}
+
+impl PageState {
+    fn tags(&mut self) -> Option<&mut Tags> {
+        let current = self.current.clone()?;
+        Some(self.tags.entry(current).or_default())
+    }
+
+    fn set_current(&mut self, current: Option<String>) {
+        self.current = current;
+        self.expect_since = false;
+        self.signature.clear();
+    }
+}
+
+/// `@Deprecated(since="9", forRemoval=true)`. The quotes can still be escaped
+fn parse_deprecated_annotation(signature: &str) -> Option<(Option<String>, bool)> {
+    let annotation = &signature[signature.rfind("@Deprecated")?..];
+    let annotation = annotation.replace("&quot;", "\"");
+    let since = annotation.find("since=\"").and_then(|start| {
+        let value = &annotation[start + 7..];
+        value.find('"').map(|end| value[..end].to_string())
+    });
+    Some((since, annotation.contains("forRemoval=true")))
+}
+
+/// Reads the tags of the type and its members out of a type's page. Keyed by the anchor of the member
+fn parse_page(html: &[u8]) -> Result<HashMap<String, Tags>, RewritingError> {
+    let state = RefCell::new(PageState::default());
+    let settings = Settings {
+        element_content_handlers: vec![
+            element!(CLASS_DESCRIPTION, |_| {
+                state.borrow_mut().set_current(Some(String::new()));
+                Ok(())
+            }),
+            element!(SUMMARY, |_| {
+                state.borrow_mut().set_current(None);
+                Ok(())
+            }),
+            element!(MEMBER_DETAIL, |element| {
+                let id = element
+                    .get_attribute("id")
+                    .or_else(|| element.get_attribute("name"));
+                if id.is_some() {
+                    state.borrow_mut().set_current(id);
+                }
+                Ok(())
+            }),
+            text!("dt", |text| {
+                let mut state = state.borrow_mut();
+                state.dt.push_str(text.as_str());
+                if text.last_in_text_node() {
+                    let dt = std::mem::take(&mut state.dt);
+                    if !dt.trim().is_empty() {
+                        state.expect_since = dt.trim() == "Since:";
+                    }
+                }
+                Ok(())
+            }),
+            text!("dd", |text| {
+                let mut state = state.borrow_mut();
+                state.dd.push_str(text.as_str());
+                if text.last_in_text_node() {
+                    let dd = std::mem::take(&mut state.dd);
+                    if state.expect_since && !dd.trim().is_empty() {
+                        state.expect_since = false;
+                        if let Some(tags) = state.tags() {
+                            tags.since = Some(dd.trim().to_string());
+                        }
+                    }
+                }
+                Ok(())
+            }),
+            text!(DEPRECATION, |text| {
+                let mut state = state.borrow_mut();
+                if let Some(tags) = state.tags() {
+                    tags.deprecated = true;
+                    if text.as_str().to_lowercase().contains("for removal") {
+                        tags.for_removal = true;
+                    }
+                }
+                Ok(())
+            }),
+            text!(SIGNATURE, |text| {
+                let mut state = state.borrow_mut();
+                if state.current.is_none() {
+                    return Ok(());
+                }
+                state.signature.push_str(text.as_str());
+                if let Some((since, for_removal)) = parse_deprecated_annotation(&state.signature) {
+                    // Wait for the rest of the annotation
+                    if since.is_none() && !state.signature.contains(')') {
+                        return Ok(());
+                    }
+                    state.signature.clear();
+                    if let Some(tags) = state.tags() {
+                        tags.deprecated = true;
+                        tags.for_removal |= for_removal;
+                        if since.is_some() {
+                            tags.deprecated_since = since;
+                        }
+                    }
+                }
+                Ok(())
+            }),
+        ],
+        ..Settings::default()
+    };
+    let mut reader = HtmlRewriter::new(settings, |_: &[u8]| {});
+    reader.write(html)?;
+    reader.end()?;
+    Ok(state.into_inner().tags)
+}
+
+/// Parses the page of every type in the javadoc. The pages are parsed on the blocking thread pool
+pub async fn generate_report(javadoc: &Path, built: DateTime<Utc>) -> Result<VersionReport, Error> {
+    let javadoc = javadoc.to_path_buf();
+    tokio::task::spawn_blocking(move || parse_types(&javadoc, &ApiModel::load(&javadoc)?, built))
+        .await
+        .map_err(|error| std::io::Error::new(ErrorKind::Other, error))?
+}
+
+fn parse_types(
+    javadoc: &Path,
+    model: &ApiModel,
+    built: DateTime<Utc>,
+) -> Result<VersionReport, Error> {
+    let files = JavadocFiles::open(javadoc)?;
+    let members: HashMap<&str, &str> = model
+        .members
+        .iter()
+        .map(|(name, path)| (path.as_str(), name.as_str()))
+        .collect();
+    let mut entries = Vec::new();
+    for (type_name, page) in model.types.iter() {
+        let html = match files.read(page)? {
+            Some(html) => html,
+            None => {
+                debug!("{page} does not exist in {}", javadoc.display());
+                continue;
+            }
+        };
+        let tags = match parse_page(html.as_bytes()) {
+            Ok(tags) => tags,
+            Err(error) => {
+                warn!("Failed to parse {page}: {error}");
+                continue;
+            }
+        };
+        for (anchor, tags) in tags {
+            if !tags.deprecated && tags.since.is_none() {
+                continue;
+            }
+            let entry = if anchor.is_empty() {
+                ReportEntry {
+                    kind: SearchKind::Type,
+                    name: type_name.clone(),
+                    path: page.clone(),
+                    tags,
+                }
+            } else {
+                let path = format!("{page}#{anchor}");
+                let name = members
+                    .get(path.as_str())
+                    .map(|name| name.to_string())
+                    .unwrap_or_else(|| format!("{type_name}#{anchor}"));
+                ReportEntry {
+                    kind: SearchKind::Member,
+                    name,
+                    path,
+                    tags,
+                }
+            };
+            entries.push(entry);
+        }
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(VersionReport { built, entries })
+}
+
+/// Generates the report of a new build so the first view does not have to
+pub async fn save_report(
+    project_location: &Path,
+    version: &str,
+    javadoc: &Path,
+    built: DateTime<Utc>,
+) -> Result<(), Error> {
+    let report = generate_report(javadoc, built).await?;
+    tokio::fs::write(
+        report_file(project_location, version),
+        serde_json::to_vec(&report)?,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Loads the saved report or generates it if the version was built after it was saved
+pub async fn load_report(
+    repository: &Repository,
+    project: &Project,
+    version: &str,
+    value: &Version,
+) -> Result<Option<VersionReport>, Error> {
+    let (path, built) = match value {
+        Version::NoBuild { .. } => return Ok(None),
+        Version::Build { path, built, .. } | Version::BuildSnapshot { path, built, .. } => {
+            (path, *built)
+        }
+    };
+    let file = report_file(
+        &repository.path.join(project_to_path(&project.name)),
+        version,
+    );
+    if file.exists() {
+        match serde_json::from_slice::<VersionReport>(&tokio::fs::read(&file).await?) {
+            Ok(report) if report.built == built => return Ok(Some(report)),
+            Ok(_) => debug!("{} is outdated", file.display()),
+            Err(error) => warn!("Failed to load {}: {error}", file.display()),
+        }
+    }
+    let report = generate_report(path, built).await?;
+    tokio::fs::write(&file, serde_json::to_vec(&report)?).await?;
+    Ok(Some(report))
+}
+
+impl ProjectReport {
+    pub fn new(
+        repository: &Repository,
+        project: &str,
+        version: &str,
+        report: VersionReport,
+    ) -> Self {
+        let mut deprecated = Vec::new();
+        let mut since: BTreeMap<String, Vec<ReportItem>> = BTreeMap::new();
+        for entry in report.entries {
+            let item = ReportItem {
+                kind: entry.kind,
+                url: repository.javadoc_url(project, version, &entry.path),
+                name: entry.name,
+                tags: entry.tags,
+            };
+            if let Some(value) = item.tags.since.clone() {
+                since.entry(value).or_default().push(item.clone());
+            }
+            if item.tags.deprecated {
+                deprecated.push(item);
+            }
+        }
+        ProjectReport {
+            repository: repository.name.clone(),
+            project: project.to_string(),
+            version: version.to_string(),
+            deprecated,
+            since,
+        }
+    }
+}
+
+/// Finds the version and builds its report
+pub async fn project_report(
+    repository: &Repository,
+    project: &str,
+    version: &str,
+) -> actix_web::Result<ProjectReport> {
+    let project = repository
+        .get_project(project)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Project not found"))?;
+    let (name, value) = project
+        .resolve_version(version)
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("{version} is not built")))?;
+    let report = load_report(repository, &project, &name, &value)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("{version} has no javadoc")))?;
+    Ok(ProjectReport::new(repository, &project.name, &name, report))
+}
+
+/// The report page. Shared between the multi and single repository modes
+pub async fn serve_report(
+    handlebars: &Handlebars<'_>,
+    site: &SiteSettings,
+    repository: &Repository,
+    project: &str,
+    version: &str,
+) -> actix_web::Result<HttpResponse> {
+    let report = project_report(repository, project, version).await?;
+    render_page(handlebars, site, "site/report.html", report)
+}
@@ -1,11 +1,16 @@
+use std::fmt::{Debug, Formatter};
 use std::path::{Path, PathBuf};
+use chrono::Duration;
 use log::info;
+use reqwest::{Client, Response};
 use serde::{Deserialize, Serialize};
 
-use tokio::io::AsyncWriteExt;
-
-use crate::project::Project;
-use crate::{ConfigRepository, Error};
+use crate::cache::AccessLog;
+use crate::database::{Database, ProjectStore};
+use crate::project::{Project, ProjectListing};
+use crate::search::SearchIndex;
+use crate::zip::JarCache;
+use crate::{metrics, Config, ConfigRepository, Error};
 
 #[derive(Debug)]
 pub struct Repository {
@@ -14,25 +19,180 @@ pub struct Repository {
     pub allows_redeploy: bool,
     pub path: PathBuf,
     pub cache: CacheRules,
+    pub auth: Option<RepositoryAuth>,
+    /// What comes before the project in the javadoc urls. Empty in single repository mode
+    pub url_prefix: String,
+    pub search: SearchIndex,
+    pub storage: Storage,
+    pub jars: JarCache,
+    pub access: AccessLog,
+    pub verification: Verification,
+    projects: ProjectStore,
+}
+
+/// Credentials sent to the upstream repository
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum RepositoryAuth {
+    Basic { username: String, password: Secret },
+    Bearer { token: Secret },
+}
+
+/// A value that can be written in the config or read from the environment or a file
+///
+/// ```toml
+/// password = "plain"
+/// password = { env = "NEXUS_PASSWORD" }
+/// password = { file = "/run/secrets/nexus" }
+/// ```
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Secret {
+    Value(String),
+    Env { env: String },
+    File { file: PathBuf },
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Secret::Value(_) => f.write_str("Secret(****)"),
+            Secret::Env { env } => write!(f, "Secret(env: {env})"),
+            Secret::File { file } => write!(f, "Secret(file: {})", file.display()),
+        }
+    }
+}
+
+impl Secret {
+    /// Reads the value. Env and File are read every time so they can be rotated without a restart
+    pub fn resolve(&self) -> Result<String, Error> {
+        match self {
+            Secret::Value(value) => Ok(value.clone()),
+            Secret::Env { env } => std::env::var(env).map_err(|_| Error::MissingEnv(env.clone())),
+            Secret::File { file } => Ok(std::fs::read_to_string(file)?.trim_end().to_string()),
+        }
+    }
+}
+
+/// How downloaded javadoc jars are verified before they are published
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Verification {
+    /// Refuse jars that have no `.sha1`, `.sha256` or `.sha512` file
+    #[serde(default)]
+    pub require_checksum: bool,
+    /// Verify the `.asc` signature against this keyring
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyring: Option<PathBuf>,
+    /// Refuse jars that have no signature. Only used if a keyring is set
+    #[serde(default)]
+    pub require_signature: bool,
+    /// The gpgv binary used to check signatures
+    #[serde(default = "default_gpgv")]
+    pub gpgv: PathBuf,
+}
+
+fn default_gpgv() -> PathBuf {
+    PathBuf::from("gpgv")
+}
+
+impl Default for Verification {
+    fn default() -> Self {
+        Self {
+            require_checksum: false,
+            keyring: None,
+            require_signature: false,
+            gpgv: default_gpgv(),
+        }
+    }
+}
+
+/// How a built javadoc is kept on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum Storage {
+    /// The jar is extracted into a folder
+    #[default]
+    Extract,
+    /// Files are read straight out of the jar
+    Jar,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CacheRules {
-    /// Amount of time in hours
+    /// Hours between checking a release for a redeploy. Releases are only checked if the repository allows redeploy
     pub time_til_update: u64,
+    /// Minutes between checking a snapshot for a new build
+    #[serde(default = "default_snapshot_update")]
+    pub snapshot_update: u64,
+    /// Builds kept of every snapshot version including the current one. Older builds can be viewed with `{version}@{build}`
+    #[serde(default = "default_snapshot_history")]
+    pub snapshot_history: usize,
+    /// Minutes between checking the maven-metadata.xml for new versions
+    #[serde(default = "default_metadata_update")]
+    pub metadata_update: u64,
+    /// Minutes until a version without javadoc is checked again. Doubles after every check that finds nothing
+    #[serde(default = "default_no_build_ttl")]
+    pub no_build_ttl: u64,
+    /// The most hours a version without javadoc waits between checks
+    #[serde(default = "default_max_no_build_ttl")]
+    pub max_no_build_ttl: u64,
+    /// Max disk space of the repository in megabytes. Least recently viewed versions are evicted first
+    #[serde(default)]
+    pub max_size: Option<u64>,
+    /// Never evicted. Either a project `group:artifact` or a version `group:artifact:version`
+    #[serde(default)]
+    pub pinned: Vec<String>,
+}
+
+fn default_snapshot_update() -> u64 {
+    60
+}
 
+fn default_snapshot_history() -> usize {
+    5
+}
+
+fn default_metadata_update() -> u64 {
+    60
+}
+
+fn default_no_build_ttl() -> u64 {
+    60
+}
+
+fn default_max_no_build_ttl() -> u64 {
+    24 * 7
 }
 
 impl Default for CacheRules {
     fn default() -> Self {
         Self {
             time_til_update: 24,
+            snapshot_update: default_snapshot_update(),
+            snapshot_history: default_snapshot_history(),
+            metadata_update: default_metadata_update(),
+            no_build_ttl: default_no_build_ttl(),
+            max_no_build_ttl: default_max_no_build_ttl(),
+            max_size: None,
+            pinned: Vec::new(),
         }
     }
 }
 
+impl CacheRules {
+    /// How long to wait before checking a version without javadoc again
+    pub fn no_build_ttl(&self, attempts: u32) -> Duration {
+        let ttl = self.no_build_ttl.saturating_mul(1 << attempts.min(16));
+        Duration::minutes(ttl.min(self.max_no_build_ttl.saturating_mul(60)) as i64)
+    }
+}
+
 impl Repository {
-    pub fn new(name: String, config: ConfigRepository, path: impl AsRef<Path>) -> Self {
+    pub fn new(
+        name: String,
+        config: ConfigRepository,
+        path: impl AsRef<Path>,
+        projects: ProjectStore,
+    ) -> Self {
         let address = if config.address.ends_with("/") {
             config.address.trim_end_matches("/").to_string()
         } else {
@@ -40,39 +200,89 @@ impl Repository {
         };
         Repository {
             path: path.as_ref().join(&name),
+            url_prefix: format!("/{name}"),
             name,
             address,
             allows_redeploy: config.allows_redeploy,
             cache: config.cache,
+            auth: config.auth,
+            search: SearchIndex::default(),
+            storage: config.storage,
+            jars: JarCache::default(),
+            access: AccessLog::default(),
+            verification: config.verification,
+            projects,
         }
     }
+    /// The url of a file inside a project's javadoc on this server
+    pub fn javadoc_url(&self, project: &str, version: &str, file: &str) -> String {
+        format!("{}/{project}/{version}/{file}", self.url_prefix)
+    }
+    /// Sends a GET request to the repository with the credentials applied
+    pub async fn get(&self, client: &Client, url: &str) -> Result<Response, Error> {
+        let request = client.get(url);
+        let request = match &self.auth {
+            None => request,
+            Some(RepositoryAuth::Basic { username, password }) => {
+                request.basic_auth(username, Some(password.resolve()?))
+            }
+            Some(RepositoryAuth::Bearer { token }) => request.bearer_auth(token.resolve()?),
+        };
+        let response = request.send().await?;
+        metrics::UPSTREAM_RESPONSES
+            .with_label_values(&[&self.name, response.status().as_str()])
+            .inc();
+        Ok(response)
+    }
     /// Returns the Project if it exists
     pub async fn get_project(&self, project: impl AsRef<str>) -> Result<Option<Project>, Error> {
-        let project_cache = self.path.join(project_to_path(project.as_ref()));
-        if !project_cache.exists() {
-            return Ok(None);
-        }
-        let project_file = project_cache.join("project.json");
-        if !project_file.exists() {
-            Ok(None)
-        } else {
-            let mut file = std::fs::File::open(project_file)?;
-            let project: Project = serde_json::from_reader(&mut file)?;
-            Ok(Some(project))
-        }
+        self.projects.get(project.as_ref())
+    }
+    /// Every project cached in the repository
+    pub fn list_projects(&self) -> Result<Vec<Project>, Error> {
+        self.projects.list()
+    }
+    /// The listing of every cached project ordered by name. Does not read the projects
+    pub fn project_listings(&self) -> Result<Vec<ProjectListing>, Error> {
+        self.projects.listings()
+    }
+    /// Amount of cached projects
+    pub fn project_count(&self) -> Result<usize, Error> {
+        self.projects.count()
     }
     pub async fn save_project(&self, project: Project) -> Result<(), Error> {
         info!("Saving project {project:?}");
-        let project_cache = self.path.join(project_to_path(project.name.as_str()));
-        if !project_cache.exists() {
-            tokio::fs::create_dir_all(&project_cache).await?;
-        }
-        let project_file = project_cache.join("project.json");
-        let mut file = tokio::fs::File::create(project_file).await?;
-        let project = serde_json::to_string_pretty(&project)?;
-        file.write_all(project.as_bytes()).await?;
-        Ok(())
+        self.projects.insert(&project)
+    }
+    /// Changes a saved project. Use this over get and save when the project could be saved in between
+    pub async fn update_project(
+        &self,
+        project: impl AsRef<str>,
+        update: impl FnMut(&mut Project),
+    ) -> Result<Option<Project>, Error> {
+        self.projects.update(project.as_ref(), update)
+    }
+    pub async fn remove_project(&self, project: impl AsRef<str>) -> Result<Option<Project>, Error> {
+        self.projects.remove(project.as_ref())
+    }
+}
+
+impl AsRef<Repository> for Repository {
+    fn as_ref(&self) -> &Repository {
+        self
+    }
+}
+
+/// Opens the database and creates every repository in the config
+pub fn open_repositories(config: &mut Config) -> Result<Vec<Repository>, Error> {
+    let database = Database::open(&config.cache)?;
+    let mut repositories = Vec::with_capacity(config.repositories.len());
+    for (name, data) in std::mem::take(&mut config.repositories) {
+        let projects = database.projects(&name, &config.cache.join(&name))?;
+        repositories.push(Repository::new(name, data, &config.cache, projects));
     }
+    repositories.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(repositories)
 }
 
 #[inline(always)]
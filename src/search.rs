@@ -0,0 +1,411 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Formatter};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::project::Version;
+use crate::repository::{project_to_path, Repository};
+use crate::zip::JarIndex;
+use crate::Error;
+
+/// The files modern javadoc generates for its own search box
+pub static PACKAGE_INDEX: &str = "package-search-index.js";
+pub static TYPE_INDEX: &str = "type-search-index.js";
+pub static MEMBER_INDEX: &str = "member-search-index.js";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum SearchKind {
+    Type,
+    Package,
+    Member,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchEntry {
+    pub kind: SearchKind,
+    pub package: String,
+    /// The class that contains the member
+    pub container: Option<String>,
+    pub name: String,
+    /// Path inside the javadoc
+    pub path: String,
+}
+
+impl SearchEntry {
+    pub fn qualified_name(&self) -> String {
+        match (&self.kind, &self.container) {
+            (SearchKind::Package, _) => self.package.clone(),
+            (SearchKind::Member, Some(container)) => {
+                format!("{}.{}#{}", self.package, container, self.name)
+            }
+            _ => format!("{}.{}", self.package, self.name),
+        }
+    }
+}
+
+/// The format of the entries inside the javadoc search files
+#[derive(Debug, Deserialize)]
+struct RawEntry {
+    /// Module
+    m: Option<String>,
+    /// Package
+    p: Option<String>,
+    /// Containing class
+    c: Option<String>,
+    /// Label
+    l: String,
+    /// Url or anchor. Javadoc 11 to 16 call it url
+    #[serde(alias = "url")]
+    u: Option<String>,
+}
+
+impl RawEntry {
+    fn package_path(&self, package: &str) -> String {
+        match &self.m {
+            Some(module) => format!("{module}/{}", package.replace('.', "/")),
+            None => package.replace('.', "/"),
+        }
+    }
+}
+
+/// The files are javascript in the form of `typeSearchIndex = [...];updateSearchResults();`
+fn parse_index<T: DeserializeOwned>(content: &str) -> Result<Vec<T>, Error> {
+    match (content.find('['), content.rfind(']')) {
+        (Some(start), Some(end)) if start < end => {
+            serde_json::from_str(&content[start..=end]).map_err(Error::from)
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// An extracted javadoc or a javadoc jar. Reading is blocking IO. Use it on the blocking thread pool
+pub enum JavadocFiles {
+    Folder(PathBuf),
+    Jar(JarIndex),
+}
+
+impl JavadocFiles {
+    pub fn open(javadoc: &Path) -> Result<Self, Error> {
+        if javadoc.is_file() {
+            Ok(JavadocFiles::Jar(JarIndex::load(javadoc)?))
+        } else {
+            Ok(JavadocFiles::Folder(javadoc.to_path_buf()))
+        }
+    }
+
+    /// Reads a file as text. None if it does not exist
+    pub fn read(&self, file: &str) -> Result<Option<String>, Error> {
+        match self {
+            JavadocFiles::Jar(jar) => Ok(jar
+                .read(file)?
+                .map(|content| String::from_utf8_lossy(&content).into_owned())),
+            JavadocFiles::Folder(folder) if folder.join(file).exists() => {
+                Ok(Some(std::fs::read_to_string(folder.join(file))?))
+            }
+            JavadocFiles::Folder(_) => Ok(None),
+        }
+    }
+}
+
+/// Parses the search files of an extracted javadoc or a javadoc jar. Missing files are skipped.
+///
+/// Blocking. Use it on the blocking thread pool
+pub fn parse_javadoc(javadoc: &Path) -> Result<Vec<SearchEntry>, Error> {
+    let files = JavadocFiles::open(javadoc)?;
+    let mut entries = Vec::new();
+    for (file, kind) in [
+        (PACKAGE_INDEX, SearchKind::Package),
+        (TYPE_INDEX, SearchKind::Type),
+        (MEMBER_INDEX, SearchKind::Member),
+    ] {
+        match files.read(file)? {
+            Some(content) => entries.extend(parse_entries(kind, &content)?),
+            None => debug!("{file} does not exist in {}", javadoc.display()),
+        }
+    }
+    Ok(entries)
+}
+
+pub fn parse_entries(kind: SearchKind, content: &str) -> Result<Vec<SearchEntry>, Error> {
+    let raw: Vec<RawEntry> = parse_index(content)?;
+    let entries = raw.into_iter().filter_map(|entry| match kind {
+        SearchKind::Package => {
+            // The "All Packages" entry has an url but is not a package
+            if entry.u.is_some() {
+                return None;
+            }
+            Some(SearchEntry {
+                kind,
+                path: format!("{}/package-summary.html", entry.package_path(&entry.l)),
+                package: entry.l,
+                container: None,
+                name: String::new(),
+            })
+        }
+        SearchKind::Type => {
+            // The "All Classes" entry has no package
+            let package = entry.p.clone()?;
+            let path = match &entry.u {
+                Some(url) => url.clone(),
+                None => format!("{}/{}.html", entry.package_path(&package), entry.l),
+            };
+            Some(SearchEntry {
+                kind,
+                package,
+                container: None,
+                name: entry.l,
+                path,
+            })
+        }
+        SearchKind::Member => {
+            let package = entry.p.clone()?;
+            let container = entry.c.clone()?;
+            let anchor = entry.u.clone().unwrap_or_else(|| entry.l.clone());
+            Some(SearchEntry {
+                kind,
+                path: format!(
+                    "{}/{container}.html#{anchor}",
+                    entry.package_path(&package)
+                ),
+                package,
+                container: Some(container),
+                name: entry.l,
+            })
+        }
+    });
+    Ok(entries.collect())
+}
+
+/// Where the parsed index of a version is saved. Next to the javadoc jar
+pub fn index_file(project_location: &Path, version: &str) -> PathBuf {
+    project_location.join(format!("{version}.search.json"))
+}
+
+struct IndexedVersion {
+    built: DateTime<Utc>,
+    entries: Vec<SearchEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    /// Lower is better
+    #[serde(skip)]
+    pub rank: u8,
+    pub repository: String,
+    pub project: String,
+    pub version: String,
+    pub kind: SearchKind,
+    pub package: String,
+    pub container: Option<String>,
+    pub name: String,
+    pub qualified_name: String,
+    pub url: String,
+}
+
+/// The search index of one repository. Keyed by project and version
+#[derive(Default)]
+pub struct SearchIndex {
+    versions: RwLock<HashMap<(String, String), IndexedVersion>>,
+    /// The packages of every indexed version of a project. Kept up to date by insert and remove
+    packages: RwLock<HashMap<String, Arc<HashSet<String>>>>,
+}
+
+impl Debug for SearchIndex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let versions = self.versions.read().map(|v| v.len()).unwrap_or_default();
+        f.debug_struct("SearchIndex")
+            .field("versions", &versions)
+            .finish()
+    }
+}
+
+impl SearchIndex {
+    pub fn insert(
+        &self,
+        project: &str,
+        version: &str,
+        built: DateTime<Utc>,
+        entries: Vec<SearchEntry>,
+    ) {
+        let mut versions = self.versions.write().unwrap();
+        let added: HashSet<String> = entries.iter().map(|entry| entry.package.clone()).collect();
+        let replaced = versions.insert(
+            (project.to_string(), version.to_string()),
+            IndexedVersion { built, entries },
+        );
+        if replaced.is_some() {
+            self.update_packages(&versions, project);
+        } else if !added.is_empty() {
+            let mut packages = self.packages.write().unwrap();
+            let packages = packages.entry(project.to_string()).or_default();
+            *packages = Arc::new(packages.union(&added).cloned().collect());
+        }
+    }
+
+    pub fn remove(&self, project: &str, version: &str) {
+        let mut versions = self.versions.write().unwrap();
+        if versions.remove(&(project.to_string(), version.to_string())).is_some() {
+            self.update_packages(&versions, project);
+        }
+    }
+
+    /// Collects the packages of the project again. Called while holding the lock on the versions
+    fn update_packages(&self, versions: &HashMap<(String, String), IndexedVersion>, project: &str) {
+        let packages: HashSet<String> = versions
+            .iter()
+            .filter(|((name, _), _)| name == project)
+            .flat_map(|(_, indexed)| indexed.entries.iter())
+            .map(|entry| entry.package.clone())
+            .collect();
+        let mut projects = self.packages.write().unwrap();
+        if packages.is_empty() {
+            projects.remove(project);
+        } else {
+            projects.insert(project.to_string(), Arc::new(packages));
+        }
+    }
+
+    /// The packages of every indexed version of the project
+    pub fn packages(&self, project: &str) -> Arc<HashSet<String>> {
+        self.packages
+            .read()
+            .unwrap()
+            .get(project)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Case insensitive search on the name and the qualified name.
+    ///
+    /// Exact matches come first, then prefixes and then anything containing the query.
+    /// Unless a project is given, the same name in multiple versions of a project only returns the newest build.
+    pub fn search(
+        &self,
+        repository: &Repository,
+        query: &str,
+        project: Option<&str>,
+        limit: usize,
+    ) -> Vec<SearchResult> {
+        let query = query.to_lowercase();
+        let versions = self.versions.read().unwrap();
+        let mut found: HashMap<(String, String), (DateTime<Utc>, SearchResult)> = HashMap::new();
+        for ((project_name, version), indexed) in versions.iter() {
+            if project.map(|p| p != project_name).unwrap_or(false) {
+                continue;
+            }
+            for entry in indexed.entries.iter() {
+                let qualified_name = entry.qualified_name();
+                let rank = match rank(&query, &entry.name)
+                    .into_iter()
+                    .chain(rank(&query, &qualified_name).map(|rank| rank + 3))
+                    .min()
+                {
+                    Some(rank) => rank * 3 + entry.kind as u8,
+                    None => continue,
+                };
+                let key = if project.is_some() {
+                    (format!("{project_name}:{version}"), qualified_name.clone())
+                } else {
+                    (project_name.clone(), qualified_name.clone())
+                };
+                if let Some((built, _)) = found.get(&key) {
+                    if *built >= indexed.built {
+                        continue;
+                    }
+                }
+                let result = SearchResult {
+                    rank,
+                    repository: repository.name.clone(),
+                    project: project_name.clone(),
+                    version: version.clone(),
+                    kind: entry.kind,
+                    package: entry.package.clone(),
+                    container: entry.container.clone(),
+                    name: entry.name.clone(),
+                    qualified_name,
+                    url: repository.javadoc_url(project_name, version, &entry.path),
+                };
+                found.insert(key, (indexed.built, result));
+            }
+        }
+        let mut results: Vec<SearchResult> =
+            found.into_values().map(|(_, result)| result).collect();
+        sort_results(&mut results);
+        results.truncate(limit);
+        results
+    }
+
+    /// Loads the saved index of every built version in the repository
+    pub fn load(&self, repository: &Repository) -> Result<(), Error> {
+        for project in repository.list_projects()? {
+            let project_location = repository.path.join(project_to_path(&project.name));
+            for (version, value) in project.versions.iter() {
+                let built = match value {
+                    Version::NoBuild { .. } => continue,
+                    Version::Build { built, .. } | Version::BuildSnapshot { built, .. } => *built,
+                };
+                let file = index_file(&project_location, version);
+                if !file.exists() {
+                    continue;
+                }
+                match serde_json::from_reader(std::fs::File::open(&file)?) {
+                    Ok(entries) => self.insert(&project.name, version, built, entries),
+                    Err(error) => warn!("Failed to load {}: {error}", file.display()),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Best rank first. Shorter names win between equal ranks
+pub fn sort_results(results: &mut [SearchResult]) {
+    results.sort_by(|a, b| {
+        a.rank
+            .cmp(&b.rank)
+            .then_with(|| a.qualified_name.len().cmp(&b.qualified_name.len()))
+    });
+}
+
+/// 0 for an exact match, 1 for a prefix and 2 for anything containing the query
+fn rank(query: &str, value: &str) -> Option<u8> {
+    let value = value.to_lowercase();
+    if value == query {
+        Some(0)
+    } else if value.starts_with(query) {
+        Some(1)
+    } else if value.contains(query) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Parses the javadoc of a freshly built version, saves it and adds it to the repository's index
+pub async fn index_version(
+    repository: &Repository,
+    project: &str,
+    version: &str,
+    javadoc: &Path,
+    built: DateTime<Utc>,
+) -> Result<(), Error> {
+    let project_location = repository.path.join(project_to_path(project));
+    let javadoc = javadoc.to_path_buf();
+    let entries = tokio::task::spawn_blocking(move || parse_javadoc(&javadoc))
+        .await
+        .map_err(|error| std::io::Error::new(ErrorKind::Other, error))??;
+    debug!("Indexed {} entries for {project} {version}", entries.len());
+    tokio::fs::write(
+        index_file(&project_location, version),
+        serde_json::to_string(&entries)?,
+    )
+    .await?;
+    repository.search.insert(project, version, built, entries);
+    Ok(())
+}
@@ -1 +1,138 @@
+use actix_web::web::ServiceConfig;
+use actix_web::{web, HttpResponse};
+use handlebars::Handlebars;
+use serde::Deserialize;
 
+use crate::diff::{serve_diff, DiffRequest};
+use crate::report::serve_report;
+use crate::site::{serve_project, ProjectQuery};
+use crate::multi::{serve_javadoc, JavadocRequest};
+use crate::project_processor::queue::BuildQueue;
+use crate::repository::Repository;
+use crate::SiteSettings;
+
+/// Same as [crate::multi::Request] but without the repository. The only configured repository is used
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    pub project: String,
+    pub version: String,
+    pub file: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiffPath {
+    pub project: String,
+    pub from: String,
+    pub to: String,
+}
+
+pub fn register_web(service: &mut ServiceConfig) {
+    // The diff and the report have to come first. The javadoc route would match them
+    service
+        .service(
+            web::resource("/{project}/diff/{from}/{to}")
+                .name("get_diff")
+                .route(web::get().to(get_diff)),
+        )
+        .service(
+            web::resource("/{project}/report/{version}")
+                .name("get_report")
+                .route(web::get().to(get_report)),
+        )
+        .service(
+            web::resource(["/{project}", "/{project}/"])
+                .name("get_project")
+                .route(web::get().to(get_project)),
+        )
+        .service(
+            web::resource([
+                "/{project}/{version}/{file:.*}",
+                "/{project}/{version}/",
+            ])
+            .name("get_javadoc")
+            .route(web::get().to(get_javadoc)),
+        );
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportPath {
+    pub project: String,
+    pub version: String,
+}
+
+pub async fn get_project(
+    requests: web::Data<BuildQueue>,
+    handlebars: web::Data<Handlebars<'static>>,
+    site: web::Data<SiteSettings>,
+    project: web::Path<String>,
+    query: web::Query<ProjectQuery>,
+    repository: web::Data<Repository>,
+) -> actix_web::Result<HttpResponse> {
+    serve_project(
+        &handlebars,
+        &site,
+        &requests,
+        &repository,
+        &project,
+        query.into_inner(),
+    )
+    .await
+}
+
+pub async fn get_report(
+    handlebars: web::Data<Handlebars<'static>>,
+    site: web::Data<SiteSettings>,
+    request: web::Path<ReportPath>,
+    repository: web::Data<Repository>,
+) -> actix_web::Result<HttpResponse> {
+    serve_report(
+        &handlebars,
+        &site,
+        &repository,
+        &request.project,
+        &request.version,
+    )
+    .await
+}
+
+pub async fn get_diff(
+    handlebars: web::Data<Handlebars<'static>>,
+    site: web::Data<SiteSettings>,
+    request: web::Path<DiffPath>,
+    repository: web::Data<Repository>,
+) -> actix_web::Result<HttpResponse> {
+    let request = request.into_inner();
+    serve_diff(
+        &handlebars,
+        &site,
+        &repository,
+        DiffRequest {
+            project: request.project,
+            from: request.from,
+            to: request.to,
+        },
+    )
+    .await
+}
+
+pub async fn get_javadoc(
+    requests: web::Data<BuildQueue>,
+    handlebars: web::Data<Handlebars<'static>>,
+    site: web::Data<SiteSettings>,
+    request: web::Path<Request>,
+    repository: web::Data<Repository>,
+) -> actix_web::Result<HttpResponse> {
+    let request = request.into_inner();
+    serve_javadoc(
+        &requests,
+        &handlebars,
+        &site,
+        repository.into_inner(),
+        JavadocRequest {
+            project: request.project,
+            version: request.version,
+            file: request.file,
+        },
+    )
+    .await
+}
@@ -1,10 +1,362 @@
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use actix_web::web::{self, Data};
 use actix_web::{get, HttpResponse};
-use actix_web::web::Data;
+use chrono::{DateTime, Utc};
 use handlebars::Handlebars;
+use log::info;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
+use crate::api::projects::version_infos;
+use crate::api::BuildStatus;
+use crate::pom::PomMetadata;
+use crate::project::Project;
+use crate::project_processor::queue::BuildQueue;
+use crate::repository::Repository;
+use crate::version::MavenVersion;
+use crate::{Error, SiteSettings};
+
+/// The folder inside the templates directory that is served under `/assets/`
+static ASSETS: &str = "assets";
+
+#[derive(Debug, Serialize)]
+pub struct ProjectSummary {
+    pub project: String,
+    pub artifact_id: String,
+    pub latest: Option<String>,
+    pub last_updated: Option<DateTime<Utc>>,
+    pub url: String,
+}
+
+/// The projects that share a groupId
+#[derive(Debug, Serialize)]
+pub struct ProjectGroup {
+    pub group_id: String,
+    pub projects: Vec<ProjectSummary>,
+}
+
+/// The cached projects of a repository grouped by groupId
+#[derive(Debug, Serialize)]
+pub struct RepositorySummary {
+    pub name: String,
+    pub url: String,
+    pub projects: usize,
+    pub groups: Vec<ProjectGroup>,
+}
+
+impl RepositorySummary {
+    pub fn new(repository: &Repository) -> Result<Self, Error> {
+        let projects = repository.project_listings()?;
+        let count = projects.len();
+        let mut groups: BTreeMap<String, Vec<ProjectSummary>> = BTreeMap::new();
+        for project in projects {
+            let (group_id, artifact_id) = project
+                .name
+                .rsplit_once(':')
+                .unwrap_or(("", project.name.as_str()));
+            groups
+                .entry(group_id.to_string())
+                .or_default()
+                .push(ProjectSummary {
+                    artifact_id: artifact_id.to_string(),
+                    url: project_url(repository, &project.name),
+                    latest: project.latest.clone(),
+                    last_updated: project.last_updated,
+                    project: project.name.clone(),
+                });
+        }
+        let groups = groups
+            .into_iter()
+            .map(|(group_id, mut projects)| {
+                projects.sort_by(|a, b| a.artifact_id.cmp(&b.artifact_id));
+                ProjectGroup { group_id, projects }
+            })
+            .collect();
+        Ok(RepositorySummary {
+            name: repository.name.clone(),
+            // In single repository mode the index is the repository page
+            url: format!("{}/", repository.url_prefix),
+            projects: count,
+            groups,
+        })
+    }
+}
+
+/// The project page on this server
+fn project_url(repository: &Repository, project: &str) -> String {
+    format!("{}/{project}", repository.url_prefix)
+}
+
+/// Registers every file in the directory over the embedded templates. Returns the amount registered
+pub fn register_templates(
+    handlebars: &mut Handlebars<'_>,
+    directory: &Path,
+) -> Result<usize, Error> {
+    let mut files = Vec::new();
+    find_templates(directory, &mut files)?;
+    for file in files.iter() {
+        // The name is the path inside the directory. Always with forward slashes like the embedded names
+        let name = file
+            .strip_prefix(directory)
+            .unwrap_or(file)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        info!("Using {} for {name}", file.display());
+        handlebars.register_template_file(&name, file)?;
+    }
+    Ok(files.len())
+}
+
+/// The assets folder holds static files and is skipped
+fn find_templates(folder: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in std::fs::read_dir(folder)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            if entry.file_name() != ASSETS {
+                find_templates(&path, files)?;
+            }
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// What every site page is rendered with. The site settings are available as `site`
+#[derive(Serialize)]
+pub struct SitePage<'a, T: Serialize> {
+    pub site: &'a SiteSettings,
+    #[serde(flatten)]
+    pub page: T,
+}
+
+/// Renders a page of the site
+pub fn render_page(
+    handlebars: &Handlebars<'_>,
+    site: &SiteSettings,
+    template: &str,
+    page: impl Serialize,
+) -> actix_web::Result<HttpResponse> {
+    let body = handlebars
+        .render(template, &SitePage { site, page })
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(body))
+}
+
+/// The stylesheet of the site pages. Rendered with the branding
+#[get("/site.css")]
+pub async fn stylesheet(
+    handlebars: Data<Handlebars<'static>>,
+    site: Data<SiteSettings>,
+) -> actix_web::Result<HttpResponse> {
+    let body = handlebars
+        .render("site/site.css", &json!({ "site": site.as_ref() }))
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/css; charset=utf-8")
+        .body(body))
+}
+
+/// Static files like the logo out of the assets folder of the templates directory
+#[get("/assets/{file:.*}")]
+pub async fn asset(
+    site: Data<SiteSettings>,
+    file: web::Path<String>,
+) -> actix_web::Result<HttpResponse> {
+    let not_found = || actix_web::error::ErrorNotFound("Asset not found");
+    let templates = site.templates.as_ref().ok_or_else(not_found)?;
+    let relative = Path::new(file.as_str());
+    // Only plain names. Anything else could leave the assets folder
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(not_found());
+    }
+    let path = templates.join(ASSETS).join(relative);
+    if !path.is_file() {
+        return Err(not_found());
+    }
+    let content = tokio::fs::read(&path).await?;
+    Ok(HttpResponse::Ok()
+        .content_type(
+            mime_guess::from_path(&path)
+                .first_or_octet_stream()
+                .to_string(),
+        )
+        .body(content))
+}
+
+/// Every repository with its cached projects
 #[get("/")]
-pub async fn index(data: Data<Handlebars<'_>>) -> HttpResponse {
-    let body = data.render("site/index.html", &json!({})).unwrap();
-    HttpResponse::Ok().body(body)
-}
\ No newline at end of file
+pub async fn index(
+    handlebars: Data<Handlebars<'static>>,
+    repositories: Data<Vec<Arc<Repository>>>,
+    site: Data<SiteSettings>,
+) -> actix_web::Result<HttpResponse> {
+    let repositories = repositories
+        .iter()
+        .map(|repository| RepositorySummary::new(repository))
+        .collect::<Result<Vec<_>, Error>>()?;
+    render_page(
+        &handlebars,
+        &site,
+        "site/index.html",
+        json!({ "repositories": repositories }),
+    )
+}
+
+/// The page of one repository. Only used in multi repository mode
+pub async fn serve_repository(
+    handlebars: &Handlebars<'_>,
+    site: &SiteSettings,
+    repository: &Repository,
+) -> actix_web::Result<HttpResponse> {
+    render_page(
+        handlebars,
+        site,
+        "site/repository.html",
+        json!({ "repository": RepositorySummary::new(repository)? }),
+    )
+}
+
+/// Picks the version shown on the project page
+#[derive(Debug, Deserialize)]
+pub struct ProjectQuery {
+    pub version: Option<String>,
+}
+
+/// A version known from the maven-metadata.xml or the cache
+#[derive(Debug, Serialize)]
+pub struct ProjectVersion {
+    pub name: String,
+    pub status: BuildStatus,
+    pub built: Option<DateTime<Utc>>,
+    /// The javadoc. None if it is not built
+    pub url: Option<String>,
+    pub report_url: Option<String>,
+    pub current: bool,
+}
+
+/// A dependency of the shown version. The url points at its javadoc on this server
+#[derive(Debug, Serialize)]
+pub struct DependencyLink {
+    pub project: String,
+    pub version: String,
+    pub scope: Option<String>,
+    pub url: String,
+    /// Whether or not the dependency has been viewed on this server before
+    pub cached: bool,
+}
+
+/// The landing page of a project
+#[derive(Debug, Serialize)]
+pub struct ProjectPage {
+    pub repository: String,
+    pub repository_url: String,
+    pub project: String,
+    pub group_id: String,
+    pub artifact_id: String,
+    pub latest: Option<String>,
+    /// The version the metadata is from. None if nothing is built
+    pub version: Option<String>,
+    pub url: Option<String>,
+    pub pom: Option<PomMetadata>,
+    pub dependencies: Vec<DependencyLink>,
+    pub versions: Vec<ProjectVersion>,
+}
+
+impl ProjectPage {
+    pub async fn new(
+        repository: &Repository,
+        queue: &BuildQueue,
+        project: &Project,
+        version: Option<&str>,
+    ) -> Result<Self, Error> {
+        let (group_id, artifact_id) = project
+            .name
+            .rsplit_once(':')
+            .unwrap_or(("", project.name.as_str()));
+        let newest = project
+            .versions
+            .iter()
+            .filter(|(_, value)| value.path().is_some())
+            .map(|(name, _)| name)
+            .max_by_key(|name| MavenVersion::parse(name));
+        // The latest version unless another was asked for. Falls back to the newest build
+        let current = version
+            .or(project.latest.as_deref())
+            .and_then(|version| project.resolve_version(version))
+            .filter(|(_, value)| value.path().is_some())
+            .or_else(|| newest.and_then(|name| project.resolve_version(name)));
+        let (version, pom) = match current {
+            Some((name, value)) => (Some(name), value.pom().cloned()),
+            None => (None, None),
+        };
+        let mut dependencies = Vec::new();
+        for dependency in pom.iter().flat_map(|pom| pom.dependencies.iter()) {
+            let version = dependency.version.as_deref().unwrap_or("latest");
+            dependencies.push(DependencyLink {
+                url: repository.javadoc_url(&dependency.project, version, ""),
+                cached: repository.get_project(&dependency.project).await?.is_some(),
+                project: dependency.project.clone(),
+                version: version.to_string(),
+                scope: dependency.scope.clone(),
+            });
+        }
+        let versions = version_infos(repository, queue, project)
+            .await
+            .into_iter()
+            .map(|info| ProjectVersion {
+                report_url: info.url.as_ref().map(|_| {
+                    format!("{}/{}/report/{}", repository.url_prefix, project.name, info.name)
+                }),
+                current: version.as_deref() == Some(info.name.as_str()),
+                name: info.name,
+                status: info.status,
+                built: info.built,
+                url: info.url,
+            })
+            .collect();
+        Ok(ProjectPage {
+            repository: repository.name.clone(),
+            repository_url: format!("{}/", repository.url_prefix),
+            project: project.name.clone(),
+            group_id: group_id.to_string(),
+            artifact_id: artifact_id.to_string(),
+            latest: project.latest.clone(),
+            url: version
+                .as_ref()
+                .map(|version| repository.javadoc_url(&project.name, version, "")),
+            version,
+            pom,
+            dependencies,
+            versions,
+        })
+    }
+}
+
+/// The project page. Shared between the multi and single repository modes
+pub async fn serve_project(
+    handlebars: &Handlebars<'_>,
+    site: &SiteSettings,
+    queue: &BuildQueue,
+    repository: &Repository,
+    project: &str,
+    query: ProjectQuery,
+) -> actix_web::Result<HttpResponse> {
+    let project = repository
+        .get_project(project)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Project not found"))?;
+    let page = ProjectPage::new(repository, queue, &project, query.version.as_deref()).await?;
+    render_page(handlebars, site, "site/project.html", page)
+}
@@ -0,0 +1,357 @@
+use std::cmp::Ordering;
+
+/// A version ordered the way Maven orders them.
+///
+/// `1.10` is newer than `1.9`, `1.0-alpha` < `1.0-beta` < `1.0-rc1` < `1.0-SNAPSHOT` < `1.0` < `1.0-sp1`
+#[derive(Debug, Clone)]
+pub struct MavenVersion {
+    items: Vec<Item>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Item {
+    Number(u64),
+    Qualifier(String),
+}
+
+/// Known qualifiers from oldest to newest. Unknown qualifiers come after all of them
+static QUALIFIERS: [&str; 7] = ["alpha", "beta", "milestone", "rc", "snapshot", "", "sp"];
+
+impl Item {
+    /// The item of a missing position. `1` equals `1.0` and `1-ga`
+    fn is_null(&self) -> bool {
+        match self {
+            Item::Number(number) => *number == 0,
+            Item::Qualifier(qualifier) => qualifier.is_empty(),
+        }
+    }
+
+    fn compare(&self, other: Option<&Item>) -> Ordering {
+        match (self, other) {
+            (Item::Number(a), Some(Item::Number(b))) => a.cmp(b),
+            (Item::Number(_), Some(Item::Qualifier(_))) => Ordering::Greater,
+            (Item::Number(a), None) => a.cmp(&0),
+            (Item::Qualifier(_), Some(Item::Number(_))) => Ordering::Less,
+            (Item::Qualifier(a), Some(Item::Qualifier(b))) => qualifier_order(a, b),
+            (Item::Qualifier(a), None) => qualifier_order(a, ""),
+        }
+    }
+}
+
+fn qualifier_order(a: &str, b: &str) -> Ordering {
+    let rank = |qualifier: &str| {
+        QUALIFIERS
+            .iter()
+            .position(|known| *known == qualifier)
+            .unwrap_or(QUALIFIERS.len())
+    };
+    rank(a).cmp(&rank(b)).then_with(|| a.cmp(b))
+}
+
+impl MavenVersion {
+    pub fn parse(version: &str) -> Self {
+        let version = version.to_lowercase();
+        let mut parts = Vec::new();
+        for part in version.split(['.', '-']) {
+            // `rc1` is `rc` followed by `1`
+            let mut start = 0;
+            let chars: Vec<(usize, char)> = part.char_indices().collect();
+            for window in chars.windows(2) {
+                let ((_, a), (index, b)) = (window[0], window[1]);
+                if a.is_ascii_digit() != b.is_ascii_digit() {
+                    parts.push(&part[start..index]);
+                    start = index;
+                }
+            }
+            parts.push(&part[start..]);
+        }
+        let mut items: Vec<Item> = Vec::with_capacity(parts.len());
+        for (index, part) in parts.iter().enumerate() {
+            let followed_by_number = parts
+                .get(index + 1)
+                .map(|next| next.starts_with(|c: char| c.is_ascii_digit()))
+                .unwrap_or(false);
+            let item = match part.parse::<u64>() {
+                Ok(number) => Item::Number(number),
+                Err(_) if part.is_empty() => Item::Number(0),
+                Err(_) => Item::Qualifier(
+                    match *part {
+                        "a" if followed_by_number => "alpha",
+                        "b" if followed_by_number => "beta",
+                        "m" if followed_by_number => "milestone",
+                        "cr" => "rc",
+                        "ga" | "final" | "release" => "",
+                        part => part,
+                    }
+                    .to_string(),
+                ),
+            };
+            items.push(item);
+        }
+        while items.last().map(Item::is_null).unwrap_or(false) {
+            items.pop();
+        }
+        MavenVersion { items }
+    }
+
+    /// Whether or not the version starts with the numbers. `2.3.1` starts with `[2]` and `[2, 3]`
+    pub fn starts_with(&self, numbers: &[u64]) -> bool {
+        numbers.iter().enumerate().all(|(index, number)| {
+            match self.items.get(index) {
+                Some(Item::Number(item)) => item == number,
+                // 2 is 2.0
+                None => *number == 0,
+                Some(Item::Qualifier(_)) => false,
+            }
+        })
+    }
+}
+
+impl Ord for MavenVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        for index in 0..self.items.len().max(other.items.len()) {
+            let ordering = match (self.items.get(index), other.items.get(index)) {
+                (Some(a), b) => a.compare(b),
+                (None, Some(b)) => b.compare(None).reverse(),
+                (None, None) => Ordering::Equal,
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+impl PartialOrd for MavenVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for MavenVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MavenVersion {}
+
+pub fn is_snapshot(version: &str) -> bool {
+    version.ends_with("-SNAPSHOT")
+}
+
+/// One set of a range. `[1.0,2.0)`, `[1.0,)`, `(,1.0]` or `[1.0]`
+#[derive(Debug, Clone)]
+pub struct Restriction {
+    /// The version and whether or not it is included
+    lower: Option<(MavenVersion, bool)>,
+    upper: Option<(MavenVersion, bool)>,
+}
+
+impl Restriction {
+    fn contains(&self, version: &MavenVersion) -> bool {
+        let lower = match &self.lower {
+            Some((lower, true)) => version >= lower,
+            Some((lower, false)) => version > lower,
+            None => true,
+        };
+        let upper = match &self.upper {
+            Some((upper, true)) => version <= upper,
+            Some((upper, false)) => version < upper,
+            None => true,
+        };
+        lower && upper
+    }
+}
+
+/// A name that stands for the newest version matching it
+#[derive(Debug, Clone)]
+pub enum VersionAlias {
+    /// The newest version that is not a snapshot
+    Release,
+    /// The newest snapshot
+    LatestSnapshot,
+    /// `2.x`, `2.3` or `2.3.x`. The newest release starting with the numbers
+    Prefix(Vec<u64>),
+    /// A Maven version range like `[1.0,2.0)` or `[1.0,2.0),[3.0,)`. The newest release inside it
+    Range(Vec<Restriction>),
+}
+
+impl VersionAlias {
+    /// None if the name is not an alias. `latest` is not an alias. It is the latest version in the maven-metadata.xml
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "release" => Some(VersionAlias::Release),
+            "latest-snapshot" => Some(VersionAlias::LatestSnapshot),
+            _ if name.starts_with(['[', '(']) => parse_range(name).map(VersionAlias::Range),
+            _ => parse_prefix(name).map(VersionAlias::Prefix),
+        }
+    }
+
+    pub fn matches(&self, version: &str) -> bool {
+        match self {
+            VersionAlias::Release => !is_snapshot(version),
+            VersionAlias::LatestSnapshot => is_snapshot(version),
+            VersionAlias::Prefix(numbers) => {
+                !is_snapshot(version) && MavenVersion::parse(version).starts_with(numbers)
+            }
+            VersionAlias::Range(restrictions) => {
+                let parsed = MavenVersion::parse(version);
+                !is_snapshot(version)
+                    && restrictions
+                        .iter()
+                        .any(|restriction| restriction.contains(&parsed))
+            }
+        }
+    }
+
+    /// The newest of the versions that matches
+    pub fn resolve<'a>(
+        &self,
+        versions: impl IntoIterator<Item = &'a String>,
+    ) -> Option<&'a String> {
+        versions
+            .into_iter()
+            .filter(|version| self.matches(version))
+            .max_by_key(|version| MavenVersion::parse(version))
+    }
+}
+
+/// `2.x`, `2.3.x` or the major and minor version `2` or `2.3`
+fn parse_prefix(name: &str) -> Option<Vec<u64>> {
+    let (numbers, wildcard) = match name.strip_suffix(".x").or_else(|| name.strip_suffix(".*")) {
+        Some(numbers) => (numbers, true),
+        None => (name, false),
+    };
+    let numbers = numbers
+        .split('.')
+        .map(|number| number.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    if wildcard || numbers.len() <= 2 {
+        Some(numbers)
+    } else {
+        None
+    }
+}
+
+fn parse_range(name: &str) -> Option<Vec<Restriction>> {
+    let mut restrictions = Vec::new();
+    let mut rest = name.trim();
+    while !rest.is_empty() {
+        let lower_inclusive = match rest.chars().next()? {
+            '[' => true,
+            '(' => false,
+            _ => return None,
+        };
+        let end = rest.find([']', ')'])?;
+        let upper_inclusive = rest[end..].starts_with(']');
+        let content = &rest[1..end];
+        let bound = |version: &str, inclusive: bool| {
+            let version = version.trim();
+            (!version.is_empty()).then(|| (MavenVersion::parse(version), inclusive))
+        };
+        let restriction = match content.split_once(',') {
+            Some((lower, upper)) => Restriction {
+                lower: bound(lower, lower_inclusive),
+                upper: bound(upper, upper_inclusive),
+            },
+            // `[1.0]` is exactly 1.0
+            None if lower_inclusive && upper_inclusive && !content.trim().is_empty() => {
+                Restriction {
+                    lower: bound(content, true),
+                    upper: bound(content, true),
+                }
+            }
+            None => return None,
+        };
+        restrictions.push(restriction);
+        rest = rest[end + 1..].trim_start();
+        if let Some(next) = rest.strip_prefix(',') {
+            rest = next.trim_start();
+        }
+    }
+    (!restrictions.is_empty()).then_some(restrictions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> MavenVersion {
+        MavenVersion::parse(version)
+    }
+
+    fn resolve(alias: &str) -> Option<String> {
+        let versions: Vec<String> = [
+            "1.0",
+            "1.9",
+            "1.10",
+            "2.0-SNAPSHOT",
+            "2.0-rc1",
+            "2.3.1",
+            "2.3.10",
+            "2.4",
+            "3.0-SNAPSHOT",
+        ]
+        .iter()
+        .map(|version| version.to_string())
+        .collect();
+        VersionAlias::parse(alias)?.resolve(versions.iter()).cloned()
+    }
+
+    #[test]
+    fn numbers_are_compared_as_numbers() {
+        assert!(version("1.10") > version("1.9"));
+        assert!(version("1.0") < version("1.0.1"));
+        assert!(version("2.0") > version("1.99.99"));
+        assert_eq!(version("1"), version("1.0.0"));
+    }
+
+    #[test]
+    fn qualifiers() {
+        assert!(version("1.0-alpha") < version("1.0-beta"));
+        assert!(version("1.0-beta") < version("1.0-milestone"));
+        assert!(version("1.0-beta") < version("1.0-rc1"));
+        assert!(version("1.0-rc1") < version("1.0-rc2"));
+        assert!(version("1.0-rc1") < version("1.0-SNAPSHOT"));
+        assert!(version("1.0-SNAPSHOT") < version("1.0"));
+        assert!(version("1.0") < version("1.0-sp1"));
+        assert!(version("1.0-alpha1") < version("1.0-a2"));
+        assert_eq!(version("1.0-cr1"), version("1.0-rc1"));
+        assert_eq!(version("1.0-ga"), version("1.0"));
+        assert_eq!(version("1.0.Final"), version("1.0"));
+    }
+
+    #[test]
+    fn named_aliases() {
+        assert_eq!(resolve("release").as_deref(), Some("2.4"));
+        assert_eq!(resolve("latest-snapshot").as_deref(), Some("3.0-SNAPSHOT"));
+    }
+
+    #[test]
+    fn prefixes() {
+        assert_eq!(resolve("1.x").as_deref(), Some("1.10"));
+        assert_eq!(resolve("2").as_deref(), Some("2.4"));
+        assert_eq!(resolve("2.3").as_deref(), Some("2.3.10"));
+        assert_eq!(resolve("2.3.x").as_deref(), Some("2.3.10"));
+        assert_eq!(resolve("2.3.*").as_deref(), Some("2.3.10"));
+        assert_eq!(resolve("4").as_deref(), None);
+        assert!(VersionAlias::parse("1.2.3").is_none());
+        assert!(VersionAlias::parse("foo").is_none());
+    }
+
+    #[test]
+    fn ranges() {
+        // Pre-releases of the upper bound are below it
+        assert_eq!(resolve("[1.0,2.0)").as_deref(), Some("2.0-rc1"));
+        assert_eq!(resolve("[1.0,2.3.1]").as_deref(), Some("2.3.1"));
+        assert_eq!(resolve("(,1.9]").as_deref(), Some("1.9"));
+        assert_eq!(resolve("(1.0,1.9)").as_deref(), None);
+        assert_eq!(resolve("[1.0]").as_deref(), Some("1.0"));
+        assert_eq!(resolve("[1.0,1.1),[2.3,2.4)").as_deref(), Some("2.3.10"));
+        assert_eq!(resolve("[5.0,)").as_deref(), None);
+        assert!(VersionAlias::parse("[1.0").is_none());
+        assert!(VersionAlias::parse("[]").is_none());
+    }
+}
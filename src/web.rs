@@ -1,3 +1,4 @@
+use std::io::ErrorKind;
 use std::sync::Arc;
 
 use actix_cors::Cors;
@@ -5,10 +6,10 @@ use actix_web::middleware::{DefaultHeaders, Logger};
 use actix_web::web::Data;
 use actix_web::{App, HttpServer};
 use handlebars::Handlebars;
-use tokio::sync::mpsc::{channel, Sender};
+use log::error;
 
-use crate::project_processor::ProjectRequest;
-use crate::repository::Repository;
+use crate::project_processor::queue::BuildQueue;
+use crate::repository::{open_repositories, Repository};
 use crate::{Config, site, Templates};
 
 macro_rules! start {
@@ -36,37 +37,65 @@ macro_rules! start {
         $server.bind($config.bind_address)?.run().await?;
     };
 }
-pub(crate) async fn start(config: Config) -> std::io::Result<()> {
-    let (sender, receiver) = channel(100);
-    let queue = Data::new(sender);
-    tokio::spawn(crate::project_processor::processor(
-        config.cache.clone(),
-        receiver,
+pub(crate) async fn start(mut config: Config) -> std::io::Result<()> {
+    let repositories = open_repositories(&mut config)
+        .map_err(|error| std::io::Error::new(ErrorKind::Other, error))?
+        .into_iter()
+        .map(|mut repository| {
+            if config.single_repo {
+                repository.url_prefix = String::new();
+            }
+            if let Err(error) = repository.search.load(&repository) {
+                error!("Failed to load the search index of {}: {error}", repository.name);
+            }
+            Arc::new(repository)
+        })
+        .collect::<Vec<_>>();
+    let queue = BuildQueue::load(config.cache.join("queue.json"), &repositories)
+        .await
+        .map_err(|error| std::io::Error::new(ErrorKind::Other, error))?;
+    let queue = Arc::new(queue);
+    crate::project_processor::processor(queue.clone(), config.workers);
+    tokio::spawn(crate::cache::sweeper(
+        repositories.clone(),
+        queue.clone(),
+        config.max_cache_size,
+        config.sweep_interval,
     ));
+    let queue = Data::from(queue);
 
     let mut reg = Handlebars::new();
     reg.register_embed_templates::<Templates>().unwrap();
+    if let Some(templates) = config.site.templates.as_ref() {
+        site::register_templates(&mut reg, templates)
+            .map_err(|error| std::io::Error::new(ErrorKind::Other, error))?;
+    }
     if config.single_repo {
-        start_single_server(config, queue).await
+        start_single_server(config, repositories, queue, reg).await
     } else {
-        start_multi_server(config, queue, reg).await
+        start_multi_server(config, repositories, queue, reg).await
     }
 }
 
 async fn start_single_server(
     config: Config,
-    queue: Data<Sender<ProjectRequest>>,
+    repositories: Vec<Arc<Repository>>,
+    queue: Data<BuildQueue>,
+    reg: Handlebars<'static>,
 ) -> std::io::Result<()> {
-    let repository = config.repositories.into_iter().next().unwrap();
-    let repository = Data::new(Repository::new(
-        repository.0,
-        repository.1,
-        config.cache.clone(),
-    ));
+    let repository = Data::from(repositories[0].clone());
+    let repositories = Data::new(repositories);
+    let handlebars = Data::new(reg);
+    let site = Data::new(config.site.clone());
+    let hooks = Data::new(config.hooks.clone());
     let server = HttpServer::new(move || {
         App::new()
+            .app_data(site.clone())
+            .app_data(hooks.clone())
             .app_data(repository.clone())
+            .app_data(repositories.clone())
             .app_data(queue.clone())
+            .app_data(handlebars.clone())
             .wrap(DefaultHeaders::new().add(("X-Powered-By", "My Javadoc powered by Actix.rs")))
             .wrap(
                 Cors::default()
@@ -76,6 +105,13 @@ async fn start_single_server(
                     .supports_credentials(),
             )
             .wrap(Logger::default())
+            .service(crate::metrics::metrics)
+            .service(site::stylesheet)
+            .service(site::asset)
+            .configure(crate::api::register_web)
+            .service(crate::hooks::deploy)
+            .configure(crate::single::register_web)
+            .service(site::index)
     });
     start!(server, config);
     Ok(())
@@ -83,18 +119,18 @@ async fn start_single_server(
 
 async fn start_multi_server(
     config: Config,
-    queue: Data<Sender<ProjectRequest>>,
+    repositories: Vec<Arc<Repository>>,
+    queue: Data<BuildQueue>,
     reg: Handlebars<'static>,
 ) -> std::io::Result<()> {
-    let repositories = config
-        .repositories
-        .into_iter()
-        .map(|(name, data)| Arc::new(Repository::new(name, data, &config.cache)))
-        .collect::<Vec<_>>();
     let repositories = Data::new(repositories);
     let handlebars = Data::new(reg);
+    let site = Data::new(config.site.clone());
+    let hooks = Data::new(config.hooks.clone());
     let server = HttpServer::new(move || {
         App::new()
+            .app_data(site.clone())
+            .app_data(hooks.clone())
             .app_data(repositories.clone())
             .app_data(queue.clone())
             .app_data(handlebars.clone())
@@ -107,6 +143,11 @@ async fn start_multi_server(
                     .supports_credentials(),
             )
             .wrap(Logger::default())
+            .service(crate::metrics::metrics)
+            .service(site::stylesheet)
+            .service(site::asset)
+            .configure(crate::api::register_web)
+            .service(crate::hooks::deploy)
             .configure(crate::multi::register_web)
             .service(site::index)
     });
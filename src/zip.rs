@@ -1,15 +1,28 @@
+use std::collections::HashMap;
 use std::fs;
-use std::fs::OpenOptions;
-use std::path::Path;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
 
+use flate2::read::DeflateDecoder;
 use log::debug;
+use zip::CompressionMethod;
 
 use crate::Error;
 
-pub fn extract(extract_to: impl AsRef<Path>, archive: impl AsRef<Path>) -> Result<(), Error> {
+/// How many jar indexes are kept in memory per repository
+static JAR_CACHE_SIZE: usize = 64;
+/// The most memory reserved up front for an entry. The size in the jar is not trusted beyond it
+static MAX_PREALLOCATION: u64 = 1024 * 1024;
+
+/// Extracts the archive. Returns the amount of bytes written
+pub fn extract(extract_to: impl AsRef<Path>, archive: impl AsRef<Path>) -> Result<u64, Error> {
     let file = std::fs::File::open(&archive)?;
 
     let mut archive = zip::ZipArchive::new(file)?;
+    let mut written = 0;
 
     for i in 0..archive.len() {
         let mut file = archive.by_index(i)?;
@@ -34,8 +47,132 @@ pub fn extract(extract_to: impl AsRef<Path>, archive: impl AsRef<Path>) -> Resul
                 }
             }
             let mut outfile = OpenOptions::new().create(true).write(true).open(&outpath)?;
-            std::io::copy(&mut file, &mut outfile)?;
+            written += std::io::copy(&mut file, &mut outfile)?;
+        }
+    }
+    Ok(written)
+}
+
+
+/// Where an entry's data is inside of the jar
+#[derive(Debug, Clone)]
+struct JarEntry {
+    data_start: u64,
+    compressed_size: u64,
+    size: u64,
+    compression: CompressionMethod,
+}
+
+/// The central directory of a jar. Lets entries be read without parsing the whole archive again
+#[derive(Debug)]
+pub struct JarIndex {
+    path: PathBuf,
+    modified: SystemTime,
+    entries: HashMap<String, JarEntry>,
+}
+
+impl JarIndex {
+    pub fn load(path: impl AsRef<Path>) -> Result<JarIndex, Error> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path)?;
+        let modified = file.metadata()?.modified()?;
+        let mut archive = zip::ZipArchive::new(file)?;
+        let mut entries = HashMap::with_capacity(archive.len());
+        for i in 0..archive.len() {
+            let file = archive.by_index_raw(i)?;
+            if file.is_dir() || file.enclosed_name().is_none() {
+                continue;
+            }
+            entries.insert(
+                file.name().to_string(),
+                JarEntry {
+                    data_start: file.data_start(),
+                    compressed_size: file.compressed_size(),
+                    size: file.size(),
+                    compression: file.compression(),
+                },
+            );
+        }
+        debug!("Indexed {} entries of {}", entries.len(), path.display());
+        Ok(JarIndex {
+            path,
+            modified,
+            entries,
+        })
+    }
+
+    /// Reads the entry. Stored entries are copied as is and deflated entries are decompressed on their own
+    pub fn read(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        let entry = match self.entries.get(name) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(entry.data_start))?;
+        let raw = file.take(entry.compressed_size);
+        let mut content = Vec::with_capacity(entry.size.min(MAX_PREALLOCATION) as usize);
+        match entry.compression {
+            CompressionMethod::Stored => {
+                let mut raw = raw;
+                raw.read_to_end(&mut content)?;
+            }
+            CompressionMethod::Deflated => {
+                DeflateDecoder::new(raw).read_to_end(&mut content)?;
+            }
+            _ => {
+                // Anything else is rare in a javadoc jar. Let the zip library handle it
+                let mut archive = zip::ZipArchive::new(File::open(&self.path)?)?;
+                archive.by_name(name)?.read_to_end(&mut content)?;
+            }
+        }
+        Ok(Some(content))
+    }
+}
+
+/// Keeps the most recently used jar indexes. A jar that changed on disk is indexed again.
+///
+/// Clones share the same indexes
+#[derive(Debug, Default, Clone)]
+pub struct JarCache {
+    indexes: Arc<Mutex<HashMap<PathBuf, (Instant, Arc<JarIndex>)>>>,
+}
+
+impl JarCache {
+    /// Reads an entry of the jar on the blocking thread pool
+    pub async fn read(&self, path: &Path, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        let cache = self.clone();
+        let (path, name) = (path.to_path_buf(), name.to_string());
+        tokio::task::spawn_blocking(move || cache.get(&path)?.read(&name))
+            .await
+            .map_err(|error| std::io::Error::new(ErrorKind::Other, error))?
+    }
+
+    /// Drops the index of a jar that is removed
+    pub fn invalidate(&self, path: &Path) {
+        self.indexes.lock().unwrap().remove(path);
+    }
+
+    pub fn get(&self, path: &Path) -> Result<Arc<JarIndex>, Error> {
+        let modified = fs::metadata(path)?.modified()?;
+        if let Some((last_used, index)) = self.indexes.lock().unwrap().get_mut(path) {
+            if index.modified == modified {
+                *last_used = Instant::now();
+                return Ok(index.clone());
+            }
+        }
+        // Loaded without holding the lock. Reading the central directory of a large jar takes a while
+        let index = Arc::new(JarIndex::load(path)?);
+        let mut indexes = self.indexes.lock().unwrap();
+        if indexes.len() >= JAR_CACHE_SIZE {
+            let oldest = indexes
+                .iter()
+                .min_by_key(|(_, (last_used, _))| *last_used)
+                .map(|(path, _)| path.clone());
+            if let Some(oldest) = oldest {
+                indexes.remove(&oldest);
+            }
         }
+        indexes.insert(path.to_path_buf(), (Instant::now(), index.clone()));
+        Ok(index)
     }
-    Ok(())
 }